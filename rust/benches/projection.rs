@@ -0,0 +1,47 @@
+//Times projecting stars, first against how Projector::project_point used to work, building and inverting a 3x3 matrix
+//for each star, then through project_stars for each projection. The spatial index and star arrays are left off so
+//every loaded star goes through the projector, and the time per loaded star is mostly its cost.
+//Run with `cargo bench --bench projection`.
+use std::{f32::consts::TAU, hint::black_box, time::Instant};
+
+use startest2_rust::new_universe;
+
+const FRAMES: u32 = 100;
+
+fn main() {
+    for render_distance in [1536.0f32, 4096.0] {
+        println!("render distance {render_distance}");
+        let mut universe = new_universe(1920, 1080, render_distance);
+        universe.set_thrust(5.0);
+        universe.tick(1.0);
+        let stars = universe.count_stars();
+        for (name, inverting) in [("inverse per star", true), ("project_point", false)] {
+            let start = Instant::now();
+            let mut drawn = 0;
+            for _ in 0..FRAMES {
+                drawn = black_box(universe.count_on_screen(black_box(inverting)));
+            }
+            let per_frame = start.elapsed().as_secs_f64() * 1e6 / FRAMES as f64;
+            let per_star = per_frame * 1e3 / stars as f64;
+            println!("{name:<20} {per_frame:>10.2} us/frame {per_star:>6.2} ns/star ({stars} loaded, {drawn} on screen)");
+        }
+
+        for (name, fov) in [("rectilinear", 0.0), ("fisheye", TAU), ("equirectangular", 0.0), ("stereographic", 4.7)] {
+            let mut universe = new_universe(1920, 1080, render_distance);
+            universe.set_projection(name, fov).unwrap();
+            universe.set_thrust(5.0);
+            universe.tick(1.0); //Loads the chunks the projection can see.
+
+            let stars = universe.count_stars();
+            let mut buffer = vec![0.0; stars * 3];
+            let start = Instant::now();
+            let mut drawn = 0;
+            for _ in 0..FRAMES {
+                drawn = black_box(universe.project_stars(&mut buffer));
+            }
+            let per_frame = start.elapsed().as_secs_f64() * 1e6 / FRAMES as f64;
+            let per_star = per_frame * 1e3 / stars as f64;
+            println!("{name:<20} {per_frame:>10.2} us/frame {per_star:>6.2} ns/star ({stars} loaded, {drawn} drawn)");
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//Compares spatial queries through the octree index against a linear scan over every loaded star.
+//Run with `cargo bench --bench spatial_index`.
+use std::{hint::black_box, time::Instant};
+
+use startest2_rust::new_universe;
+
+const QUERIES: u32 = 2000;
+
+fn time<F: FnMut(u32)>(name: &str, mut f: F) {
+    let start = Instant::now();
+    for i in 0..QUERIES {
+        f(i);
+    }
+    let per_query = start.elapsed().as_secs_f64() * 1e6 / QUERIES as f64;
+    println!("{name:<32} {per_query:>10.2} us/query");
+}
+
+fn main() {
+    for render_distance in [1536.0f32, 4096.0] {
+        let mut universe = new_universe(1920, 1080, render_distance);
+        universe.set_thrust(5.0);
+        universe.tick(1.0);
+        println!("render distance {render_distance}, {} stars", universe.count_stars());
+
+        for indexed in [false, true] {
+            universe.set_spatial_index(indexed);
+            let label = if indexed {"octree"} else {"linear"};
+
+            time(&format!("{label} sphere r=256"), |i| {
+                let offset = (i % 16) as f64 * 64.0;
+                black_box(universe.stars_in_sphere(offset, -offset, render_distance as f64 / 2.0, 256.0));
+            });
+
+            time(&format!("{label} ray cast"), |i| {
+                let spread = (i % 32) as f32 / 32.0 - 0.5;
+                black_box(universe.ray_cast(0.0, 0.0, 0.0, spread, -spread, 1.0, render_distance, 16.0));
+            });
+
+            let mut buffer = vec![0.0; universe.count_stars() * 3];
+            time(&format!("{label} project_stars"), |_| {
+                black_box(universe.project_stars(&mut buffer));
+            });
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//Compares projecting through the chunk iterator against the flat struct-of-arrays layout, and what sorting the stars
+//by depth adds on top.
+//Run with `cargo bench --bench star_layout`.
+use std::{hint::black_box, time::Instant};
+
+use startest2_rust::new_universe;
+
+const FRAMES: u32 = 200;
+
+fn main() {
+    for render_distance in [1536.0, 4096.0] {
+        let mut universe = new_universe(1920, 1080, render_distance);
+        universe.set_thrust(5.0);
+        universe.tick(1.0);
+        println!("render distance {render_distance}, {} stars", universe.count_stars());
+
+        let mut buffer = vec![0.0; universe.count_stars() * 3];
+        for (arrays, order) in [(false, "none"), (true, "none"), (true, "back_to_front")] {
+            universe.set_star_arrays(arrays);
+            universe.set_depth_order(order).unwrap();
+            let label = match (arrays, order) {
+                (false, _) => "chunk iterator",
+                (true, "none") => "struct of arrays",
+                _ => "arrays, sorted"
+            };
+
+            let start = Instant::now();
+            let mut drawn = 0;
+            for _ in 0..FRAMES {
+                drawn = black_box(universe.project_stars(&mut buffer));
+            }
+            let per_frame = start.elapsed().as_secs_f64() * 1e6 / FRAMES as f64;
+            println!("{label:<20} {per_frame:>10.2} us/frame ({drawn} drawn)");
+        }
+    }
+}
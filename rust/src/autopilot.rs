@@ -0,0 +1,79 @@
+use crate::{camera::Camera, vector3::{PointVector, WorldVector}};
+
+const MAX_TURN_RATE: f32 = 0.05; //Same as the fastest the keyboard controls turn.
+const MAX_THRUST: f32 = 10.0;
+const THRUST_ANGLE: f32 = 0.3; //Only thrust once the camera is within this many radians of the way it needs to push.
+const SPEED_RESPONSE: f32 = 10.0; //Ticks over which to correct the speed.
+const ARRIVE_DISTANCE: f32 = 1.0;
+const ARRIVE_SPEED: f32 = 0.05;
+const AIM_THRESHOLD: f32 = 0.05; //Fraction of full thrust below which the camera stays pointed at the target.
+
+//Flies the camera to a point by setting the same rotation and thrust targets the user controls do.
+pub struct Autopilot {
+    pub target: WorldVector,
+    pub standoff: f32 //Distance from the target to stop at.
+}
+
+impl Autopilot {
+    pub fn new(target: WorldVector, standoff: f32) -> Self {
+        Autopilot { target, standoff: standoff.max(0.0) } //It can't stop closer than on the target.
+    }
+
+    //Sets the camera's controls for this tick. target is relative to the floating origin. Returns false once the
+    //camera has stopped at the standoff distance.
+    pub fn steer(&self, camera: &mut Camera, target: PointVector) -> bool {
+        camera.strafe = 0.0; //Only the main engine is used.
+        camera.lift = 0.0;
+        let to_target = target - camera.pos;
+        let dirs = camera.ori.get_mat().to_vectors_vert();
+        let dist = to_target.magnitude();
+        let remaining = dist - self.standoff;
+
+        if remaining.abs() < ARRIVE_DISTANCE && camera.vel.magnitude() < ARRIVE_SPEED {
+            camera.set_turn_rate(PointVector::zeros());
+            camera.cut_thrust();
+            return false;
+        }
+
+        //Fastest speed we can still stop from in the remaining distance, braking at half of full reverse thrust. Close
+        //in, ease off in proportion to the distance so the camera settles instead of hunting back and forth.
+        let flight = camera.flight;
+        let max_thrust = MAX_THRUST*camera.thrust_scale; //What the engine actually delivers in cruise flight.
+        let braking = max_thrust/flight.mass/2.0;
+        let target_speed = (2.0*braking*remaining.abs()).sqrt()
+            .min(max_thrust/flight.resistance)
+            .min(remaining.abs()/SPEED_RESPONSE) * remaining.signum();
+
+        //The thrust that holds the target velocity against drag and gravity and closes the gap to it. Any sideways drift
+        //is part of the gap, which matters when there's no drag to get rid of it.
+        let along = to_target.normalised();
+        let force = camera.vel*flight.resistance - camera.gravity*flight.mass + (along*target_speed - camera.vel)*(flight.mass/SPEED_RESPONSE);
+        let aim = if force.magnitude() < max_thrust*AIM_THRESHOLD {
+            to_target //Nothing much to do, so keep looking at the target.
+        } else if force*(along*2.0 + dirs[2]) < 0.0 {
+            -force //Slow down with reverse thrust rather than turning round, and don't flip while pushing sideways.
+        } else {
+            force
+        };
+        let (x, y, z) = (aim*dirs[0], aim*dirs[1], aim*dirs[2]);
+
+        //The turn rate lags its target by rpy_factor ticks, so this gain turns as fast as possible without overshooting.
+        //On top of that, turn with the target as it swings past, or the camera would trail behind it.
+        let gain = 1.0/(4.0*flight.rpy_factor);
+        let swing = camera.vel.cross(along)/dist.max(ARRIVE_DISTANCE); //Angular velocity of the line to the target.
+        let yaw_error = x.atan2(z);
+        let pitch_error = (-y).atan2((x*x + z*z).sqrt());
+        camera.set_turn_rate(PointVector::new(
+            0.0,
+            (pitch_error*gain + swing*dirs[0]).clamp(-MAX_TURN_RATE, MAX_TURN_RATE),
+            (yaw_error*gain + swing*dirs[1]).clamp(-MAX_TURN_RATE, MAX_TURN_RATE)
+        ));
+
+        camera.thrust = if yaw_error.abs().max(pitch_error.abs()) > THRUST_ANGLE {
+            0.0 //Turn on the spot first.
+        } else {
+            (force*dirs[2]/camera.thrust_scale).clamp(-MAX_THRUST, MAX_THRUST)
+        };
+        true
+    }
+}
@@ -0,0 +1,166 @@
+use crate::vector3::{ChunkVector, PointVector, WorldVector};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+//Little endian helpers shared by the binary save formats. Every format starts with four magic bytes and a version byte.
+pub trait ByteWriter {
+    fn put_header(&mut self, magic: &[u8; 4], version: u8);
+    fn put_u16(&mut self, value: u16);
+    fn put_u32(&mut self, value: u32);
+    fn put_i32(&mut self, value: i32);
+    fn put_f32(&mut self, value: f32);
+    fn put_f64(&mut self, value: f64);
+    fn put_str(&mut self, value: &str);
+
+    fn put_point(&mut self, value: PointVector) {
+        value.to_array().into_iter().for_each(|c| self.put_f32(c));
+    }
+
+    fn put_chunk(&mut self, value: ChunkVector) {
+        value.to_array().into_iter().for_each(|c| self.put_i32(c));
+    }
+
+    fn put_world(&mut self, value: WorldVector) {
+        value.to_array().into_iter().for_each(|c| self.put_f64(c));
+    }
+}
+
+impl ByteWriter for Vec<u8> {
+    fn put_header(&mut self, magic: &[u8; 4], version: u8) {
+        self.extend_from_slice(magic);
+        self.push(version);
+    }
+
+    fn put_u16(&mut self, value: u16) {
+        self.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u32(&mut self, value: u32) {
+        self.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_i32(&mut self, value: i32) {
+        self.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_f32(&mut self, value: f32) {
+        self.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_f64(&mut self, value: f64) {
+        self.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_str(&mut self, value: &str) {
+        self.put_u32(value.len() as u32);
+        self.extend_from_slice(value.as_bytes());
+    }
+}
+
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    name: &'static str //What is being read, for error messages.
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8], name: &'static str) -> Self {
+        ByteReader { bytes, pos: 0, name }
+    }
+
+    pub fn header(&mut self, magic: &[u8; 4]) -> Result<u8, String> { //Returns the version.
+        if self.take(4)? != magic {
+            return Err(format!("Not a StarTest {}", self.name));
+        }
+        self.u8()
+    }
+
+    pub fn finish(&self) -> Result<(), String> {
+        if !self.is_empty() {
+            return Err(format!("Trailing bytes after {}", self.name));
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool { //True once everything has been read.
+        self.pos == self.bytes.len()
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| format!("{} is truncated", self.name))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| format!("{} has text that is not valid UTF-8", self.name))
+    }
+
+    pub fn point(&mut self) -> Result<PointVector, String> {
+        Ok(PointVector::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    pub fn chunk(&mut self) -> Result<ChunkVector, String> {
+        Ok(ChunkVector::new(self.i32()?, self.i32()?, self.i32()?))
+    }
+
+    pub fn world(&mut self) -> Result<WorldVector, String> {
+        Ok(WorldVector::new(self.f64()?, self.f64()?, self.f64()?))
+    }
+}
+
+pub fn to_base64(bytes: &[u8]) -> String { //URL safe and without padding.
+    let mut text = String::new();
+    for group in bytes.chunks(3) {
+        let bits = group.iter().enumerate().fold(0u32, |bits, (i, byte)| bits | (*byte as u32) << (16 - 8*i));
+        for i in 0..=group.len() {
+            text.push(BASE64[(bits >> (18 - 6*i) & 63) as usize] as char);
+        }
+    }
+    text
+}
+
+pub fn from_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = vec!();
+    for group in text.as_bytes().chunks(4) {
+        if group.len() == 1 {
+            return None;
+        }
+        let mut bits = 0u32;
+        for (i, c) in group.iter().enumerate() {
+            bits |= (BASE64.iter().position(|a| a == c)? as u32) << (18 - 6*i);
+        }
+        for i in 0..group.len() - 1 {
+            bytes.push((bits >> (16 - 8*i)) as u8);
+        }
+    }
+    Some(bytes)
+}
@@ -1,5 +1,6 @@
-use crate::{vector3::PointVector, orientation::Orientation, viewport::Viewport, matrix::Matrix3};
+use crate::{collision::{entry, star_radius, CLEARANCE}, bytes::{ByteReader, ByteWriter}, flight::{FlightModel, MAX_BETA}, vector3::PointVector, orientation::Orientation, quaternion::Quaternion, viewport::Viewport, matrix::Matrix3, projection::Projection};
 
+#[derive(Clone)]
 pub struct Camera {
     pub pos: PointVector,
     pub vel: PointVector,
@@ -8,20 +9,34 @@ pub struct Camera {
     pub target_rpy_vel: PointVector,
     pub rvp: Viewport, //Render Viewport for drawing stars.
     pub cvp: Viewport, //Chunk Viewport for loading chunks.
-    pub thrust: f32,
+    pub thrust: f32, //Along the camera's Z axis.
+    pub strafe: f32, //Along X.
+    pub lift: f32, //Along Y.
+    pub thrust_scale: f32, //Set by the universe each tick for cruise flight. 1 otherwise.
+    pub flight: FlightModel,
+    pub gravity: PointVector, //Gravitational acceleration at the end of the last tick.
 }
 
-pub struct Projector<'a> {
+pub struct Projector<'a, P: Projection> {
     cam: &'a Camera,
-    xcol: PointVector,
-    ycol: PointVector,
-    total: PointVector,
+    view: Matrix3, //Takes a point relative to the camera to one along its right, up and forward directions.
+    projection: P,
+    beta: PointVector, //The camera's velocity over the speed of light. Zero unless the flight model has one.
+    gamma: f32,
 }
 
-const RPY_FACTOR: f32 = 20.0;
-const MASS: f32 = 100.0; //20kg. Assuming one unit is a meter.
-const RESISTANCE_FACTOR: f32 = 1.0;
+pub struct Frustum { //The region of space that can end up on screen, used to cull whole groups of stars at once.
+    pos: PointVector,
+    dirs: [PointVector; 3],
+    tan_x: f32,
+    tan_y: f32,
+    depth: f32,
+}
 
+const SOFTENING: f32 = 1.0;
+const STEP_ORBIT_FRACTION: f32 = 0.05;
+const STEP_DISTANCE_FRACTION: f32 = 0.1; //Never move more than this fraction of the distance to a star in one step.
+const MAX_GRAVITY_STEPS: u32 = 256;
 
 impl Camera {
     pub fn new(max_bound: f32, fov: f32, render_distance: f32) -> Self {
@@ -36,64 +51,319 @@ impl Camera {
             target_rpy_vel: PointVector::zeros(),
             rvp: Viewport::fov_maxbound(fov, max_bound),
             cvp: Viewport::fov_alpha(fov, render_distance),
-            thrust: 0.0
+            thrust: 0.0,
+            strafe: 0.0,
+            lift: 0.0,
+            thrust_scale: 1.0,
+            flight: FlightModel::arcade(),
+            gravity: PointVector::zeros()
         }
     }
 
-    pub fn tick(&mut self, delta: f32) {
-        
-        self.pos += self.vel*delta;
+    //stars are the positions and masses of the stars close enough to pull on the camera. Gravity is integrated with
+    //leapfrog, kicking the velocity by half a step's pull either side of each move, which keeps orbits from spiralling
+    //in or out the way plain Euler steps do. Close to a star the tick is split into smaller steps.
+    pub fn tick(&mut self, delta: f32, stars: &[(PointVector, f32)]) {
+        let flight = self.flight;
+        let start = self.pos;
+        if flight.gravity != 0.0 {
+            let steps = Self::gravity_steps(self.pos, self.vel, stars, flight.gravity, delta);
+            let step = delta/steps as f32;
+            for _ in 0..steps {
+                self.vel += self.gravity*(step/2.0);
+                self.pos += self.vel*step;
+                self.gravity = Self::pull(self.pos, stars, flight.gravity);
+                self.vel += self.gravity*(step/2.0);
+            }
+        } else {
+            self.gravity = PointVector::zeros();
+            self.pos += self.vel*delta;
+        }
         self.ori.rotate(self.rpy_vel * delta);
 
-        let delta_vel = self.target_rpy_vel - self.rpy_vel;
-        self.rpy_vel += delta_vel*(delta/RPY_FACTOR);
+        if flight.rotational_inertia {
+            self.rpy_vel += self.target_rpy_vel*(delta/flight.rpy_factor);
+        } else {
+            let delta_vel = self.target_rpy_vel - self.rpy_vel;
+            self.rpy_vel += delta_vel*(delta/flight.rpy_factor);
+        }
         
-        let resistance = -self.vel*RESISTANCE_FACTOR;
+        let resistance = -self.vel*flight.resistance;
 
-        let direction_vec = self.ori.get_mat().to_vectors_vert()[2];
+        let dirs = self.ori.get_mat().to_vectors_vert();
+        let direction_vec = dirs[2];
+        let controls = [self.strafe, self.lift, self.thrust];
+        let thrust = dirs[0]*self.strafe + dirs[1]*self.lift + direction_vec*self.thrust;
+        let mut force = thrust*self.thrust_scale + resistance;
+        if flight.assist > 0.0 { //Flight assist fires against drift along any axis the controls leave alone.
+            for (axis, control) in dirs.iter().zip(controls) {
+                if control == 0.0 {
+                    force = force - *axis*((self.vel * *axis)*flight.assist);
+                }
+            }
+        }
 
-        self.vel += (direction_vec*(self.thrust) + resistance)*delta/MASS;
+        self.vel += force*delta/flight.mass;
 
         let dp = self.vel * direction_vec; //dot product
-        let fov;
-        if dp > 0.0 {
-           fov = 90.0f32.to_radians() + dp.powf(1.2)/100.0;
-        } else {
-            fov = 90.0f32.to_radians();
+        self.set_fov(flight.fov(dp));
+        if flight.light_speed > 0.0 {
+            self.cvp.set_fov_constant_alpha(flight.chunk_fov(dp));
         }
 
-        //self.vel = self.ori.get_mat().to_vectors_vert()[2] * self.speed;
+        if flight.avoidance > 0.0 {
+            self.avoid(start, stars, delta);
+        }
+    }
 
-        //let fov = MAX_ANGLE*(self.speed-3.0)/(self.speed+0.1);
+    //Stops the camera at the surface of any star it would have flown into this tick, then brakes just hard enough that
+    //it would come to rest at the surface of any star it's heading for within the avoidance distance.
+    fn avoid(&mut self, start: PointVector, stars: &[(PointVector, f32)], delta: f32) {
+        for (star, mass) in stars {
+            let radius = star_radius(*mass);
+            if let Some(t) = entry(start, self.pos, *star, radius) {
+                let hit = start + (self.pos - start)*t;
+                let out = (hit - *star).normalised();
+                self.pos = *star + out*(radius*CLEARANCE); //Also pushes the camera out if it started inside.
+                let inward = self.vel*out;
+                if inward < 0.0 {
+                    self.vel += out*-inward;
+                }
+            }
+        }
+        for (star, mass) in stars {
+            let radius = star_radius(*mass);
+            let from_star = self.pos - *star;
+            let dist = from_star.magnitude();
+            if dist >= radius*self.flight.avoidance || dist == 0.0 {
+                continue;
+            }
+            let out = from_star/dist;
+            let closing = -(self.vel*out);
+            if closing > 0.0 {
+                let gap = (dist - radius).max(f32::EPSILON);
+                let braking = closing*closing/(2.0*gap);
+                self.vel += out*(braking*delta).min(closing);
+            }
+        }
+    }
+
+    fn gravity_steps(pos: PointVector, vel: PointVector, stars: &[(PointVector, f32)], gravity: f32, delta: f32) -> u32 {
+        let speed = vel.magnitude();
+        let mut steps = 1.0f32;
+        for (star, mass) in stars {
+            let d = *star - pos;
+            let dist = (d*d + SOFTENING*SOFTENING).sqrt();
+            let orbit_time = (dist*dist*dist/(gravity.abs()*mass)).sqrt(); //Roughly how long a close orbit takes, over 2pi.
+            steps = steps.max(delta/(STEP_ORBIT_FRACTION*orbit_time)).max(speed*delta/(STEP_DISTANCE_FRACTION*dist));
+        }
+        (steps.ceil() as u32).min(MAX_GRAVITY_STEPS)
+    }
+
+    fn pull(pos: PointVector, stars: &[(PointVector, f32)], gravity: f32) -> PointVector {
+        let mut accel = PointVector::zeros();
+        for (star, mass) in stars {
+            let d = *star - pos;
+            let dist_sq = d*d + SOFTENING*SOFTENING; //Softened so passing through a star doesn't fling the camera away.
+            accel += d*(gravity*mass/(dist_sq*dist_sq.sqrt()));
+        }
+        accel
+    }
+
+    //Turns at the given roll, pitch and yaw rates. With rotational inertia this works out the torque needed to get there
+    //in the same time the arcade model would.
+    pub fn set_turn_rate(&mut self, rate: PointVector) {
+        self.target_rpy_vel = if self.flight.rotational_inertia {rate - self.rpy_vel} else {rate};
+    }
+
+    pub fn cut_thrust(&mut self) {
+        self.thrust = 0.0;
+        self.strafe = 0.0;
+        self.lift = 0.0;
+    }
+
+    pub fn set_fov(&mut self, fov: f32) {
         self.rvp.set_fov_constant_max_bound(fov);
         self.cvp.set_fov_constant_alpha(fov);
     }
+
+    //Errs on cameras that can't be drawn or flown, like ones read from a damaged save.
+    pub fn check(&self) -> Result<(), String> {
+        let points = [self.pos, self.vel, self.rpy_vel, self.target_rpy_vel, self.gravity];
+        let numbers = [self.thrust, self.strafe, self.lift, self.rvp.get_maxbound(), self.cvp.get_alpha()];
+        if points.iter().any(|p| !p.to_array().iter().all(|n| n.is_finite())) || numbers.iter().any(|n| !n.is_finite()) || !self.ori.get_mat().values.iter().all(|n| n.is_finite()) {
+            return Err("Every number in the camera must be finite".to_string());
+        }
+        let fov = |vp: &Viewport| vp.get_fov() > 0.0 && vp.get_fov() < std::f32::consts::PI;
+        if !fov(&self.rvp) || !fov(&self.cvp) || self.rvp.get_maxbound() <= 0.0 || self.cvp.get_alpha() <= 0.0 {
+            return Err("The camera's fovs must be between 0 and 180° and its sizes above 0".to_string());
+        }
+        self.flight.check()
+    }
+
+    //Writes everything the flight model depends on, so read_state gives back a camera that flies identically.
+    pub fn write_state(&self, bytes: &mut Vec<u8>) {
+        bytes.put_point(self.pos);
+        bytes.put_point(self.vel);
+        let quat = self.ori.get_quaternion();
+        [quat.w, quat.x, quat.y, quat.z].into_iter().for_each(|c| bytes.put_f32(c));
+        bytes.put_point(self.rpy_vel);
+        bytes.put_point(self.target_rpy_vel);
+        bytes.put_f32(self.thrust);
+        bytes.put_f32(self.strafe);
+        bytes.put_f32(self.lift);
+        bytes.put_f32(self.rvp.get_fov());
+        bytes.put_f32(self.rvp.get_maxbound());
+        bytes.put_f32(self.cvp.get_fov());
+        bytes.put_f32(self.cvp.get_alpha());
+        self.flight.write(bytes);
+        bytes.put_point(self.gravity);
+    }
+
+    pub fn read_state(reader: &mut ByteReader) -> Result<Self, String> {
+        Ok(Camera {
+            pos: reader.point()?,
+            vel: reader.point()?,
+            ori: Orientation::from_quaternion(Quaternion::new(reader.f32()?, reader.f32()?, reader.f32()?, reader.f32()?)),
+            rpy_vel: reader.point()?,
+            target_rpy_vel: reader.point()?,
+            thrust: reader.f32()?,
+            strafe: reader.f32()?,
+            lift: reader.f32()?,
+            rvp: Viewport::fov_maxbound(reader.f32()?, reader.f32()?),
+            cvp: Viewport::fov_alpha(reader.f32()?, reader.f32()?),
+            thrust_scale: 1.0,
+            flight: FlightModel::read(reader)?,
+            gravity: reader.point()?
+        })
+    }
 }
 
-impl<'a> Projector<'a> {
-    pub fn new(camera: &'a Camera, vp: &Viewport) -> Self{
+impl<'a, P: Projection> Projector<'a, P> {
+    pub fn new(camera: &'a Camera, projection: P) -> Self{
         let camera_dirs = camera.ori.get_mat().to_vectors_vert();
-        let alpha = vp.get_alpha();
+        let light_speed = camera.flight.light_speed;
+        let mut beta = if light_speed > 0.0 {camera.vel/light_speed} else {PointVector::zeros()};
+        if beta.magnitude() > MAX_BETA {
+            beta = beta.normalised()*MAX_BETA;
+        }
 
         Projector {
             cam: camera,
-            xcol: camera_dirs[0] * -1.0,
-            ycol: camera_dirs[1] * 1.0, //Y coords inverted because PIXI's Y is down and mine is up.
-            total: camera_dirs[2] * alpha
+            view: Matrix3::from_vectors_horiz(camera_dirs),
+            projection,
+            beta,
+            gamma: 1.0/(1.0 - beta*beta).sqrt()
         }
     }
 
+    pub fn is_relativistic(&self) -> bool { //If so, stars can be drawn well away from where they really are.
+        self.beta*self.beta > 0.0
+    }
+
+    //Returns how much the star's light is blueshifted, as a factor on its frequency and colour temperature, and how
+    //much brighter it looks. Both are 1 at rest or with no speed of light set.
+    pub fn doppler(&self, point: &PointVector) -> (f32, f32) {
+        if !self.is_relativistic() {
+            return (1.0, 1.0);
+        }
+        let dir = (*point - self.cam.pos).normalised();
+        let shift = self.gamma*(1.0 + self.beta*dir);
+        (shift, shift.powi(4))
+    }
+
+    //Where the star appears to be. Moving bunches stars up towards the direction of travel.
+    fn aberrate(&self, point: &PointVector) -> PointVector {
+        if !self.is_relativistic() {
+            return *point;
+        }
+        let rel = *point - self.cam.pos;
+        let dist = rel.magnitude();
+        let speed = self.beta.magnitude();
+        if dist == 0.0 {
+            return *point;
+        }
+        let (dir, axis) = (rel/dist, self.beta/speed);
+        let along = dir*axis;
+        let across = dir - axis*along;
+        let k = 1.0 + speed*along;
+        let seen = axis*((along + speed)/k) + across/(self.gamma*k);
+        self.cam.pos + seen*dist
+    }
+
+    //Returns how much to scale the point by and its offset from the middle of the screen, as Projection::project does.
     pub fn project_point(&self, point: &PointVector) -> Option<PointVector> {
-        let lambda = *point - self.cam.pos; //The direction vector from the camera to the point.
-        let unsolved_inv = Matrix3::from_vectors_vert([lambda, self.xcol, self.ycol]).invert();
-        match unsolved_inv {
-            Some(m) => {
-                let solved = m.solve_vec(self.total);
-                return Some(solved);
-            },
-            None => {
-                return None;
-            },
-        }
-    }
-}
\ No newline at end of file
+        let lambda = self.aberrate(point) - self.cam.pos; //The direction vector from the camera to the point.
+        self.projection.project(self.view.solve_vec(lambda))
+    }
+}
+
+impl Frustum {
+    pub fn new(camera: &Camera, width: u32, height: u32) -> Self {
+        let alpha = camera.rvp.get_alpha();
+        Frustum {
+            pos: camera.pos,
+            dirs: camera.ori.get_mat().to_vectors_vert(),
+            tan_x: width as f32 / 2.0 / alpha,
+            tan_y: height as f32 / 2.0 / alpha,
+            depth: camera.cvp.get_alpha()
+        }
+    }
+
+    pub fn intersects_box(&self, lo: PointVector, hi: PointVector) -> bool { //Conservative. Only rejects boxes fully outside one plane.
+        let mut outside = [true; 6];
+        for i in 0..8 {
+            let corner = PointVector::new(
+                if i & 1 == 0 {lo.x} else {hi.x},
+                if i & 2 == 0 {lo.y} else {hi.y},
+                if i & 4 == 0 {lo.z} else {hi.z}
+            );
+            let rel = corner - self.pos;
+            let (x, y, z) = (rel*self.dirs[0], rel*self.dirs[1], rel*self.dirs[2]);
+            let planes = [
+                z < 0.0,
+                z > self.depth,
+                x > z*self.tan_x,
+                -x > z*self.tan_x,
+                y > z*self.tan_y,
+                -y > z*self.tan_y
+            ];
+            for (out, plane) in outside.iter_mut().zip(planes) {
+                *out &= plane;
+            }
+        }
+        !outside.contains(&true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, prelude::StdRng, Rng};
+
+    use super::*;
+    use crate::projection::Rectilinear;
+
+    #[test]
+    fn projection_matches_solving_for_each_star() { //How project_point used to work.
+        let mut camera = Camera::new(1920.0, 1.2, 1536.0);
+        camera.pos = PointVector::new(10.0, -4.0, 3.0);
+        camera.ori = Orientation::new(PointVector::new(0.3, -1.1, 2.0));
+        let projector = Projector::new(&camera, Rectilinear::new(&camera.rvp));
+        let dirs = camera.ori.get_mat().to_vectors_vert();
+        let total = dirs[2]*camera.rvp.get_alpha();
+
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let point = PointVector::new(rng.gen_range(-500.0..500.0), rng.gen_range(-500.0..500.0), rng.gen_range(-500.0..500.0));
+            let solved = Matrix3::from_vectors_vert([point - camera.pos, dirs[0]*-1.0, dirs[1]]).invert().unwrap().solve_vec(total);
+            match projector.project_point(&point) {
+                Some(p) => {
+                    for (a, b) in p.to_array().into_iter().zip(solved.to_array()) {
+                        assert!((a - b).abs() <= 1e-3*b.abs().max(1.0), "{:?} != {:?}", p, solved);
+                    }
+                },
+                None => assert!(solved.x <= 0.0)
+            }
+        }
+    }
+}
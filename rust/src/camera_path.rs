@@ -0,0 +1,138 @@
+use crate::{quaternion::Quaternion, vector3::WorldVector};
+
+//Camera paths are saved as plain text, one keyframe per line:
+//
+//    startest-path 1
+//    # time x y z qw qx qy qz fov
+//    key 0 0 0 0 1 0 0 0 90
+//    key 120 0 0 2000 0.924 0 0.383 0 75
+//
+//The first line is the header and format version. Lines starting with # and blank lines are ignored. Each key line
+//has the time in ticks (the same units as Universe::tick's delta), the world position, the orientation as a unit
+//quaternion and the field of view in degrees. Keys may be in any order.
+const HEADER: &str = "startest-path";
+const VERSION: u32 = 1;
+
+#[derive(Clone, Copy)]
+pub struct Keyframe {
+    pub time: f32,
+    pub pos: WorldVector,
+    pub rot: Quaternion,
+    pub fov: f32 //Radians.
+}
+
+#[derive(Default)]
+pub struct CameraPath {
+    keyframes: Vec<Keyframe> //Sorted by time.
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, keyframe: Keyframe) { //Replaces any keyframe at the same time.
+        self.keyframes.retain(|k| k.time != keyframe.time);
+        let i = self.keyframes.partition_point(|k| k.time < keyframe.time);
+        self.keyframes.insert(i, keyframe);
+    }
+
+    pub fn len(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    pub fn start_time(&self) -> f32 {
+        self.keyframes.first().map_or(0.0, |k| k.time)
+    }
+
+    pub fn end_time(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time)
+    }
+
+    //Position follows a Catmull-Rom spline through the keyframes, orientation is slerped and the FOV is lerped.
+    //Times outside the path are clamped to its ends.
+    pub fn sample(&self, time: f32) -> Option<Keyframe> {
+        let keys = &self.keyframes;
+        let last = keys.len().checked_sub(1)?;
+        if time <= keys[0].time || last == 0 {
+            return Some(Keyframe { time, ..keys[0] });
+        }
+        if time >= keys[last].time {
+            return Some(Keyframe { time, ..keys[last] });
+        }
+
+        let i = keys.partition_point(|k| k.time <= time) - 1;
+        let (k1, k2) = (&keys[i], &keys[i + 1]);
+        let k0 = &keys[i.saturating_sub(1)];
+        let k3 = &keys[(i + 2).min(last)];
+        let t = (time - k1.time)/(k2.time - k1.time);
+
+        Some(Keyframe {
+            time,
+            pos: catmull_rom(k0.pos, k1.pos, k2.pos, k3.pos, t as f64),
+            rot: k1.rot.slerp(&k2.rot, t),
+            fov: k1.fov + (k2.fov - k1.fov)*t
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{} {}\n# time x y z qw qx qy qz fov\n", HEADER, VERSION);
+        for k in &self.keyframes {
+            text += &format!(
+                "key {} {} {} {} {} {} {} {} {}\n",
+                k.time, k.pos.x, k.pos.y, k.pos.z, k.rot.w, k.rot.x, k.rot.y, k.rot.z, k.fov.to_degrees()
+            );
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate()
+            .map(|(n, line)| (n + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        match lines.next() {
+            Some((_, line)) if line == format!("{} {}", HEADER, VERSION) => {},
+            Some((n, line)) => return Err(format!("Line {}: expected \"{} {}\", found \"{}\"", n, HEADER, VERSION, line)),
+            None => return Err("Empty camera path".to_string())
+        }
+
+        let mut path = CameraPath::new();
+        for (n, line) in lines {
+            let mut words = line.split_whitespace();
+            if words.next() != Some("key") {
+                return Err(format!("Line {}: expected a key", n));
+            }
+            let values = words
+                .map(|word| word.parse::<f64>().map_err(|_| format!("Line {}: \"{}\" is not a number", n, word)))
+                .collect::<Result<Vec<f64>, String>>()?;
+            let [time, x, y, z, qw, qx, qy, qz, fov] = values[..] else {
+                return Err(format!("Line {}: expected 9 numbers, found {}", n, values.len()));
+            };
+            if values.iter().any(|v| !v.is_finite()) {
+                return Err(format!("Line {}: every number must be finite", n));
+            }
+            if fov <= 0.0 || fov >= 180.0 {
+                return Err(format!("Line {}: the fov must be between 0 and 180°", n));
+            }
+
+            path.add(Keyframe {
+                time: time as f32,
+                pos: WorldVector::new(x, y, z),
+                rot: Quaternion::new(qw as f32, qx as f32, qy as f32, qz as f32).normalised(),
+                fov: (fov as f32).to_radians()
+            });
+        }
+        Ok(path)
+    }
+}
+
+fn catmull_rom(p0: WorldVector, p1: WorldVector, p2: WorldVector, p3: WorldVector, t: f64) -> WorldVector {
+    let t2 = t*t;
+    let t3 = t2*t;
+    (p1*2.0 + (p2 - p0)*t + (p0*2.0 - p1*5.0 + p2*4.0 - p3)*t2 + (p1*3.0 - p0 - p2*3.0 + p3)*t3) * 0.5
+}
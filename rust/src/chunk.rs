@@ -4,14 +4,17 @@ use std::iter::Iterator;
 use rand::{SeedableRng, prelude::StdRng, Rng};
 use crate::vector3::{PointVector, ChunkVector, CHUNK_SIZE};
 
+pub const GENERATOR_VERSION: u8 = 1; //Bump whenever a change moves existing stars, so saved locations can tell.
 
 pub struct Chunk {
-    pub stars: Vec<PointVector>,
+    pub stars: Vec<PointVector>, //Relative to the floating origin the chunk was made or last rebased with.
+    pub ids: Vec<u32>, //Index of each star within the chunk, see StarId.
     pub pos: ChunkVector
 }
 
 impl Chunk {
-    pub fn populate(chunk: ChunkVector) -> Self {
+    pub fn populate(chunk: ChunkVector, origin: ChunkVector) -> Self {
+        let relative = chunk - origin; //Small enough to be exact as an f32.
         let seed = Self::gen_seed(chunk);
         let mut rng = StdRng::seed_from_u64(seed);
         let num_stars:u32 = rng.gen_range(0..5);
@@ -23,28 +26,46 @@ impl Chunk {
                 rng.gen()
             );
 
-            let absolute_pos = PointVector::new(
-                (offsets.x + chunk.x as f32)*(CHUNK_SIZE as f32),
-                (offsets.y + chunk.y as f32)*(CHUNK_SIZE as f32),
-                (offsets.z + chunk.z as f32)*(CHUNK_SIZE as f32),
+            let relative_pos = PointVector::new(
+                (offsets.x + relative.x as f32)*(CHUNK_SIZE as f32),
+                (offsets.y + relative.y as f32)*(CHUNK_SIZE as f32),
+                (offsets.z + relative.z as f32)*(CHUNK_SIZE as f32),
             );
 
-            stars.push(absolute_pos);
+            stars.push(relative_pos);
         
         });
 
         return Self {
+            ids: (0..num_stars).collect(),
             stars,
             pos: chunk,
         }
 
     }
 
-    fn gen_seed(coords: ChunkVector) -> u64 {
+    pub fn rebase(&mut self, shift: PointVector) { //Moves the stars when the floating origin moves by shift.
+        for star in &mut self.stars {
+            *star = *star - shift;
+        }
+    }
+
+    //In solar masses, from 0.1 to about 3 with small stars the most common. Worked out from a hash of the star's id
+    //rather than drawn from the chunk's generator, so stars added later don't change the masses of the others.
+    pub fn star_mass(chunk: ChunkVector, index: u32) -> f32 {
+        let mut hash = Self::gen_seed(chunk) ^ (index as u64).wrapping_mul(0x9E3779B97F4A7C15); //SplitMix64.
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D049BB133111EB);
+        hash ^= hash >> 31;
+        let u = (hash >> 40) as f32 / (1u64 << 24) as f32;
+        0.1/(1.0 - 0.99*u).powf(1.0/1.35) //Salpeter-like power law.
+    }
+
+    fn gen_seed(coords: ChunkVector) -> u64 { //Wraps like release builds always have, so far away seeds don't change.
         let p = coords.x;
-        let q = coords.y * 1218/*889438532*/;
-        let r = coords.z * 3930/*374324760*/;
-        let total:i64 = (p+q+r) as i64;
+        let q = coords.y.wrapping_mul(1218/*889438532*/);
+        let r = coords.z.wrapping_mul(3930/*374324760*/);
+        let total:i64 = p.wrapping_add(q).wrapping_add(r) as i64;
         unsafe {
             std::mem::transmute::<i64, u64>(total)
         }
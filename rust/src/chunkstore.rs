@@ -1,14 +1,33 @@
-use std::{slice::Iter, mem::replace};
+use std::{collections::HashMap, slice::Iter, mem::{replace, size_of}};
 use itertools::iproduct;
 
-use crate::{vector3::{ChunkVector, PointVector, Vector3}, camera::Camera, chunk::Chunk};
+use crate::{vector3::{ChunkVector, PointVector, Vector3, WorldVector, CHUNK_SIZE}, camera::Camera, chunk::Chunk, orientation::Orientation, viewport::Viewport, octree::{Octree, in_sphere, ray_hit}, soa::StarArrays, overlay::{Overlay, StarId}, stats::{GenerationHistogram, UniverseStats}, now_ms};
 
 pub struct ChunkStore {
+    origin: ChunkVector, //The floating origin. Star positions are relative to this chunk's corner.
     lo: ChunkVector,
     hi: ChunkVector,
     delta: ChunkVector, // = hi-lo
     num_stars: usize,
-    chunks: Vec<Box<Chunk>>
+    chunks: Vec<Box<Chunk>>,
+    index: Option<Octree>, //Optional spatial index, kept in step with the loaded chunks.
+    arrays: Option<StarArrays>, //Optional flat copy of the stars for fast projection, also kept in step.
+    overlay: Overlay,
+    prefetch: Option<Prefetch>,
+    all_round: bool, //Whether to load every chunk within the render distance, not just the ones in view.
+    generated: usize, //Chunks made and reused by the last update. Prefetched chunks count as reused.
+    reused: usize,
+    histogram: GenerationHistogram
+}
+
+//Chunks made ahead of time for somewhere the camera is about to be, so update can pick them up instead of generating
+//them all at once when it gets there.
+struct Prefetch {
+    origin: ChunkVector, //The floating origin the chunks were made relative to.
+    lo: ChunkVector,
+    hi: ChunkVector,
+    next: usize, //How far through the bounds in x, y, z order the chunks have been made.
+    chunks: HashMap<ChunkVector, Box<Chunk>>
 }
 pub struct ChunkStoreIter<'a>{
     chunks_iter: Iter<'a, Box<Chunk>>,
@@ -17,33 +36,58 @@ pub struct ChunkStoreIter<'a>{
 }
 
 impl ChunkStore {
-    pub fn start(cam: &Camera) -> Self {
-        let (lo, hi, delta) = Self::get_gen_bounds(cam);
+    pub fn start(cam: &Camera, origin: ChunkVector, overlay: Overlay) -> Self {
+        let start_time = now_ms();
+        let (lo, hi, delta) = Self::get_gen_bounds(cam.pos, &cam.ori, &cam.cvp, origin, false);
 
         let mut chunks = Vec::with_capacity((delta.x * delta.y * delta.z) as usize);
         let mut num_stars = 0;
 
         for (x, y, z) in iproduct!(lo.x..hi.x, lo.y..hi.y, lo.z..hi.z) {
-            let chunk = Self::make_chunk(x, y, z);
+            let chunk = Self::make_chunk(&overlay, origin, x, y, z);
             num_stars += chunk.stars.len();
             chunks.push(chunk);
         }
 
+        let mut histogram = GenerationHistogram::default();
+        histogram.record(now_ms() - start_time);
+
         Self {
+            generated: chunks.len(),
+            reused: 0,
+            histogram,
+            origin,
             lo,
             hi,
             delta,
             chunks,
-            num_stars
+            num_stars,
+            index: None,
+            arrays: None,
+            overlay,
+            prefetch: None,
+            all_round: false
         }
     }
 
+    //Throws away every loaded chunk and generates them again, as start would. Stars in chunks that were loaded before a
+    //rebase can be a rounding error away from freshly generated ones, so this gives a state that can be reproduced.
+    pub fn restart(&mut self, cam: &Camera) {
+        let mut store = Self::start(cam, self.origin, std::mem::take(&mut self.overlay));
+        store.set_indexed(self.index.is_some());
+        store.set_arrays(self.arrays.is_some());
+        store.all_round = self.all_round;
+        *self = store;
+    }
+
     pub fn update(&mut self, cam: &Camera) { //Consumes the ChunkStore.
-        let (new_lo, new_hi, new_delta) = Self::get_gen_bounds(&cam);
+        let start_time = now_ms();
+        let (new_lo, new_hi, new_delta) = Self::get_gen_bounds(cam.pos, &cam.ori, &cam.cvp, self.origin, self.all_round);
 
 
         let new_chunk_arr = Vec::with_capacity((new_delta.x * new_delta.y * new_delta.z) as usize);
         let mut num_stars = 0;
+        let mut reused = 0;
 
         let old_stars = replace(&mut self.chunks, new_chunk_arr);
         
@@ -59,52 +103,353 @@ impl ChunkStore {
                     chunk.pos.x == x && chunk.pos.y < y ||
                     chunk.pos.x == x && chunk.pos.y == y && chunk.pos.z < z
                 {
+                    Self::unload(&mut self.index, &mut self.arrays, &chunk);
                     current_chunk = chunk_iter.next();
                     continue;
                 } else if chunk.pos.x == x && chunk.pos.y == y && chunk.pos.z == z { //Chunk found
                     this_chunk = Some(chunk);
                     current_chunk = chunk_iter.next();
                     break;
-                } else { //No chunk found, make a new one.
-                    current_chunk = chunk_iter.next();
+                } else { //No chunk found, make a new one. Keep this one as it may be needed further on.
+                    current_chunk = Some(chunk);
                     break; //Important do not remove.
                 }
             }
 
             match this_chunk {
                 Some(chunk) => {
+                    reused += 1;
                     num_stars += chunk.stars.len();
                     self.chunks.push(chunk);
                 },
                 None => {
-                    let chunk = Self::make_chunk(x, y, z);
+                    let chunk = match self.take_prefetched(ChunkVector::new(x, y, z)) {
+                        Some(chunk) => {
+                            reused += 1;
+                            chunk
+                        },
+                        None => Self::make_chunk(&self.overlay, self.origin, x, y, z)
+                    };
+                    Self::load(&mut self.index, &mut self.arrays, &chunk);
                     num_stars += chunk.stars.len();
                     self.chunks.push(chunk);
                 },
             }
         }
 
+        if let Some(chunk) = current_chunk { //Anything left over has been unloaded.
+            Self::unload(&mut self.index, &mut self.arrays, &chunk);
+        }
+        for chunk in chunk_iter {
+            Self::unload(&mut self.index, &mut self.arrays, &chunk);
+        }
+
         self.lo = new_lo;
         self.hi = new_hi;
         self.delta = new_delta;
         self.num_stars = num_stars;
+        self.generated = self.chunks.len() - reused;
+        self.reused = reused;
+        if self.generated > 0 { //Updates that only reuse chunks would drag the timings down.
+            self.histogram.record(now_ms() - start_time);
+        }
+    }
+
+    //Makes up to max_chunks of the chunks a camera at pos (relative to origin) would load, for update to use once the
+    //store has been rebased there. Returns true once they've all been made. Asking for somewhere else starts again.
+    pub fn prefetch(&mut self, pos: PointVector, ori: &Orientation, cvp: &Viewport, origin: ChunkVector, max_chunks: usize) -> bool {
+        let (lo, hi, delta) = Self::get_gen_bounds(pos, ori, cvp, origin, self.all_round);
+        let prefetch = match &mut self.prefetch {
+            Some(prefetch) if prefetch.origin == origin && prefetch.lo == lo && prefetch.hi == hi => prefetch,
+            prefetch => prefetch.insert(Prefetch { origin, lo, hi, next: 0, chunks: HashMap::new() })
+        };
+
+        let total = (delta.x * delta.y * delta.z) as usize;
+        let end = total.min(prefetch.next + max_chunks);
+        for (x, y, z) in iproduct!(lo.x..hi.x, lo.y..hi.y, lo.z..hi.z).skip(prefetch.next).take(end - prefetch.next) {
+            prefetch.chunks.insert(ChunkVector::new(x, y, z), Self::make_chunk(&self.overlay, origin, x, y, z));
+        }
+        prefetch.next = end;
+        end == total
+    }
+
+    pub fn clear_prefetch(&mut self) {
+        self.prefetch = None;
+    }
+
+    fn take_prefetched(&mut self, pos: ChunkVector) -> Option<Box<Chunk>> {
+        let prefetch = self.prefetch.as_mut()?;
+        let mut chunk = prefetch.chunks.remove(&pos)?;
+        chunk.rebase((self.origin - prefetch.origin).corner());
+        Some(chunk)
+    }
+
+    pub fn stats(&self) -> UniverseStats {
+        let chunk_size = CHUNK_SIZE as f64;
+        let chunk_memory: usize = self.chunks.iter()
+            .chain(self.prefetch.iter().flat_map(|prefetch| prefetch.chunks.values()))
+            .map(|chunk| size_of::<Chunk>() + chunk.stars.capacity()*size_of::<PointVector>() + chunk.ids.capacity()*size_of::<u32>())
+            .sum();
+        let memory = size_of::<Self>() +
+            self.chunks.capacity()*size_of::<Box<Chunk>>() +
+            chunk_memory +
+            self.index.as_ref().map_or(0, Octree::memory_usage) +
+            self.arrays.as_ref().map_or(0, StarArrays::memory_usage);
+
+        UniverseStats::new(
+            self.chunks.len(),
+            self.chunks.len() as f64 * chunk_size * chunk_size * chunk_size,
+            self.num_stars,
+            memory,
+            self.generated,
+            self.reused,
+            self.histogram.clone()
+        )
+    }
+
+    fn load(index: &mut Option<Octree>, arrays: &mut Option<StarArrays>, chunk: &Chunk) {
+        if let Some(index) = index {
+            index.insert(chunk);
+        }
+        if let Some(arrays) = arrays {
+            arrays.insert(chunk);
+        }
+    }
+
+    fn unload(index: &mut Option<Octree>, arrays: &mut Option<StarArrays>, chunk: &Chunk) {
+        if let Some(index) = index {
+            index.remove(chunk.pos);
+        }
+        if let Some(arrays) = arrays {
+            arrays.remove(chunk.pos);
+        }
+    }
+
+    pub fn set_indexed(&mut self, indexed: bool) {
+        if !indexed {
+            self.index = None;
+        } else if self.index.is_none() {
+            let mut index = Octree::new(self.origin);
+            for chunk in &self.chunks {
+                index.insert(chunk);
+            }
+            self.index = Some(index);
+        }
+    }
+
+    //For projections that can see behind the camera. Takes effect on the next update.
+    pub fn set_all_round(&mut self, all_round: bool) {
+        self.all_round = all_round;
+    }
+
+    pub fn index(&self) -> Option<&Octree> {
+        self.index.as_ref()
+    }
+
+    pub fn set_arrays(&mut self, enabled: bool) {
+        if !enabled {
+            self.arrays = None;
+        } else if self.arrays.is_none() {
+            let mut arrays = StarArrays::new();
+            for chunk in &self.chunks {
+                arrays.insert(chunk);
+            }
+            self.arrays = Some(arrays);
+        }
+    }
+
+    pub fn arrays(&self) -> Option<&StarArrays> {
+        self.arrays.as_ref()
+    }
+
+    pub fn for_each_in_sphere<F: FnMut(&PointVector)>(&self, center: PointVector, radius: f32, f: F) {
+        match &self.index {
+            Some(index) => index.for_each_in_sphere(center, radius, f),
+            None => self.iter().filter(|star| in_sphere(star, center, radius)).for_each(f)
+        }
+    }
+
+    pub fn ray_cast(&self, origin: PointVector, dir: PointVector, max_dist: f32, hit_radius: f32) -> Option<(PointVector, f32)> { //Returns the nearest star hit and its distance.
+        match &self.index {
+            Some(index) => index.ray_cast(origin, dir, max_dist, hit_radius),
+            None => self.iter()
+                .filter_map(|star| ray_hit(origin, dir, star, hit_radius).map(|dist| (*star, dist)))
+                .filter(|(_, dist)| *dist <= max_dist)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+        }
+    }
+
+    fn make_chunk(overlay: &Overlay, origin: ChunkVector, x:i32, y:i32, z:i32) -> Box<Chunk>{
+        let mut chunk = Chunk::populate(ChunkVector::new(x, y, z), origin);
+        overlay.apply(&mut chunk, origin);
+        Box::new(chunk)
+    }
+
+    fn chunk_index(&self, pos: ChunkVector) -> Option<usize> { //Chunks are stored densely in x, y, z order.
+        let rel = pos - self.lo;
+        if
+            rel.x < 0 || rel.y < 0 || rel.z < 0 ||
+            rel.x >= self.delta.x || rel.y >= self.delta.y || rel.z >= self.delta.z
+        {
+            return None;
+        }
+        Some(((rel.x * self.delta.y + rel.y) * self.delta.z + rel.z) as usize)
     }
 
-    fn make_chunk(x:i32, y:i32, z:i32) -> Box<Chunk>{
-        Box::new(Chunk::populate(ChunkVector::new(x, y, z)))
+    pub fn get_chunk(&self, pos: ChunkVector) -> Option<&Chunk> {
+        self.chunk_index(pos).map(|i| &*self.chunks[i])
+    }
+
+    pub fn overlay(&self) -> &Overlay {
+        &self.overlay
+    }
+
+    pub fn overlay_mut(&mut self) -> &mut Overlay { //Call refresh_chunk or refresh_all afterwards so loaded chunks pick up the edits.
+        &mut self.overlay
+    }
+
+    pub fn set_overlay(&mut self, overlay: Overlay) {
+        self.overlay = overlay;
+        self.refresh_all();
+    }
+
+    pub fn refresh_chunk(&mut self, pos: ChunkVector) { //Regenerates a loaded chunk.
+        if let Some(prefetch) = &mut self.prefetch {
+            prefetch.chunks.remove(&pos); //Made again if it's needed.
+        }
+        if let Some(i) = self.chunk_index(pos) {
+            let chunk = Self::make_chunk(&self.overlay, self.origin, pos.x, pos.y, pos.z);
+            Self::unload(&mut self.index, &mut self.arrays, &self.chunks[i]);
+            Self::load(&mut self.index, &mut self.arrays, &chunk);
+            self.num_stars = self.num_stars - self.chunks[i].stars.len() + chunk.stars.len();
+            self.chunks[i] = chunk;
+        }
+    }
+
+    pub fn refresh_all(&mut self) {
+        self.prefetch = None;
+        let positions: Vec<ChunkVector> = self.chunks.iter().map(|chunk| chunk.pos).collect();
+        for pos in positions {
+            self.refresh_chunk(pos);
+        }
+    }
+
+    pub fn star_position(&self, id: &StarId) -> Option<PointVector> { //Generates the star's chunk if it isn't loaded.
+        let find = |chunk: &Chunk| chunk.ids.iter().position(|i| *i == id.index).map(|i| chunk.stars[i]);
+        match self.get_chunk(id.chunk()) {
+            Some(chunk) => find(chunk),
+            None => find(&Self::make_chunk(&self.overlay, self.origin, id.cx, id.cy, id.cz))
+        }
+    }
+
+    pub fn find_star(&self, point: PointVector, radius: f32) -> Option<(StarId, PointVector)> { //Finds the closest loaded star within the radius.
+        let mut best: Option<(StarId, PointVector, f32)> = None;
+        self.for_each_near(point, radius, false, |id, star, dist_sq| {
+            if best.is_none_or(|(_, _, best_sq)| dist_sq < best_sq) {
+                best = Some((id, *star, dist_sq));
+            }
+        });
+        best.map(|(id, star, _)| (id, star))
+    }
+
+    //Stars within the radius and their masses. Only the chunks in view are loaded, so this generates any others it
+    //needs. Otherwise everything behind the camera would be missing.
+    pub fn masses_near(&self, point: PointVector, radius: f32) -> Vec<(PointVector, f32)> {
+        let mut found = vec!();
+        self.for_each_near(point, radius, true, |id, star, _| found.push((*star, Chunk::star_mass(id.chunk(), id.index))));
+        found
+    }
+
+    pub fn stars_near(&self, point: PointVector, radius: f32) -> Vec<(StarId, PointVector)> { //Generates chunks like masses_near.
+        let mut found = vec!();
+        self.for_each_near(point, radius, true, |id, star, _| found.push((id, *star)));
+        found
+    }
+
+    //Calls f with the id, position and squared distance of every star within the radius, generating chunks that
+    //aren't loaded if asked to. Only looks in the chunks the sphere touches, so it's cheap for small radii.
+    fn for_each_near<F: FnMut(StarId, &PointVector, f32)>(&self, point: PointVector, radius: f32, generate: bool, mut f: F) {
+        let offset = PointVector::new(radius, radius, radius);
+        let lo = self.chunk_of(point - offset);
+        let hi = self.chunk_of(point + offset);
+
+        for (x, y, z) in iproduct!(lo.x..=hi.x, lo.y..=hi.y, lo.z..=hi.z) {
+            let made;
+            let chunk = match self.get_chunk(ChunkVector::new(x, y, z)) {
+                Some(chunk) => chunk,
+                None if generate => {
+                    made = Self::make_chunk(&self.overlay, self.origin, x, y, z);
+                    &made
+                },
+                None => continue
+            };
+            for (star, id) in chunk.stars.iter().zip(&chunk.ids) {
+                let d = *star - point;
+                let dist_sq = d*d;
+                if dist_sq <= radius*radius {
+                    f(StarId::new(chunk.pos, *id), star, dist_sq);
+                }
+            }
+        }
+    }
+
+    pub fn for_each_star<F: FnMut(StarId, &PointVector)>(&self, mut f: F) { //Every loaded star, with its id.
+        for chunk in &self.chunks {
+            for (star, id) in chunk.stars.iter().zip(&chunk.ids) {
+                f(StarId::new(chunk.pos, *id), star);
+            }
+        }
     }
 
     pub fn count_stars(&self) -> usize{
         self.num_stars
     } 
 
-    fn get_gen_bounds(cam: &Camera) -> (ChunkVector, ChunkVector, ChunkVector) { //Returns the low bounds, high bounds and delta.
-        let cam_dirs = cam.ori.get_mat().to_vectors_vert();
-        let max_point = cam.pos + cam_dirs[2] * cam.cvp.get_alpha(); //Find the endpoint.
-        let maxbound_half = cam.cvp.get_maxbound() / 2.0;
+    pub fn origin(&self) -> ChunkVector {
+        self.origin
+    }
+
+    pub fn chunk_of(&self, point: PointVector) -> ChunkVector { //Absolute coordinates of the chunk containing a relative point.
+        ChunkVector::from_point(point) + self.origin
+    }
+
+    pub fn to_relative(&self, world: WorldVector) -> PointVector {
+        (world - self.origin.world_corner()).to_point()
+    }
+
+    pub fn to_world(&self, point: PointVector) -> WorldVector {
+        self.origin.world_corner() + WorldVector::from_point(point)
+    }
+
+    pub fn rebase(&mut self, new_origin: ChunkVector) -> PointVector { //Moves the floating origin, returning how far relative positions shift.
+        let shift = (new_origin - self.origin).corner();
+        self.origin = new_origin;
+        for chunk in &mut self.chunks {
+            chunk.rebase(shift);
+        }
+        if let Some(index) = &mut self.index {
+            index.rebase(new_origin, shift);
+        }
+        if let Some(arrays) = &mut self.arrays {
+            arrays.rebase(shift);
+        }
+        shift
+    }
+
+    //Returns the low bounds, high bounds and delta of the chunks a camera at pos would load.
+    //All round, that's every chunk within the render distance in any direction.
+    fn get_gen_bounds(pos: PointVector, ori: &Orientation, cvp: &Viewport, origin: ChunkVector, all_round: bool) -> (ChunkVector, ChunkVector, ChunkVector) {
+        if all_round {
+            let reach = PointVector::new(1.0, 1.0, 1.0)*cvp.get_alpha();
+            let (lo, hi) = (ChunkVector::from_point(pos - reach), ChunkVector::from_point(pos + reach) + ChunkVector::new(1, 1, 1));
+            return (lo + origin, hi + origin, hi - lo);
+        }
+        let cam_dirs = ori.get_mat().to_vectors_vert();
+        let max_point = pos + cam_dirs[2] * cvp.get_alpha(); //Find the endpoint.
+        let maxbound_half = cvp.get_maxbound() / 2.0;
 
 
-        let cam_point = ChunkVector::from_point(cam.pos);
+        let cam_point = ChunkVector::from_point(pos);
         //its only 4 lines.
         let point_a  = ChunkVector::from_point(max_point - cam_dirs[0]*maxbound_half + cam_dirs[1]*maxbound_half);
         let point_b  = ChunkVector::from_point(max_point + cam_dirs[0]*maxbound_half + cam_dirs[1]*maxbound_half);
@@ -115,6 +460,7 @@ impl ChunkStore {
         //Add padding
         let hi = hi + ChunkVector::new(1, 1, 1); //To make high bounds exclusive.
         let delta = hi - lo;
+        let (lo, hi) = (lo + origin, hi + origin);
         return (lo, hi, delta);
     }
 
@@ -148,4 +494,32 @@ impl<'a> Iterator for ChunkStoreIter<'a> {
         let star = next_star.unwrap();
         return Some(star);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn only_generates_new_chunks() {
+        let mut camera = Camera::new(800.0, 1.2, 1536.0);
+        let mut store = ChunkStore::start(&camera, ChunkVector::new(0, 0, 0), Overlay::new());
+        let updates = |store: &ChunkStore| store.stats().generation_histogram().iter().sum::<u32>();
+        let before = updates(&store);
+        store.update(&camera);
+        assert_eq!((store.generated, updates(&store)), (0, before));
+
+        for rpy in [(0.0, 0.1, 0.1), (0.5, -0.3, 0.4), (0.0, 0.0, 3.0)] { //Changes the shape of the loaded region, leaving gaps.
+            let old: HashSet<_> = store.chunks.iter().map(|chunk| chunk.pos).collect();
+            camera.ori = Orientation::new(PointVector::new(rpy.0, rpy.1, rpy.2));
+            camera.pos += PointVector::new(40.0, 90.0, 150.0);
+            store.update(&camera);
+            let new = store.chunks.iter().filter(|chunk| !old.contains(&chunk.pos)).count();
+            assert_eq!(store.generated, new);
+            assert!(store.reused > 0);
+        }
+        assert_eq!(updates(&store), before + 3);
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::VecDeque;
+
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{overlay::StarId, vector3::PointVector};
+
+pub const MAX_STAR_RADIUS: f32 = 2.5; //star_radius of the heaviest star Chunk::star_mass gives.
+pub const CLEARANCE: f32 = 1.001; //Avoidance stops the camera this many radii out, so it never counts as touching.
+const MAX_EVENTS: usize = 64; //Oldest events are dropped past this if nobody reads them.
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct StarContact {
+    pub star: StarId,
+    pub entered: bool //False when the camera leaves the star again.
+}
+
+pub fn star_radius(mass: f32) -> f32 { //Roughly how main sequence stars scale, one unit for a solar mass.
+    mass.powf(0.8)
+}
+
+//Returns how far along the move from start to end the camera first touches the sphere, as a fraction. 0 if it starts
+//inside.
+pub fn entry(start: PointVector, end: PointVector, center: PointVector, radius: f32) -> Option<f32> {
+    let from_center = start - center;
+    let c = from_center*from_center - radius*radius;
+    if c <= 0.0 {
+        return Some(0.0);
+    }
+    let step = end - start;
+    let a = step*step;
+    let b = from_center*step;
+    if a == 0.0 || b >= 0.0 {
+        return None; //Not moving, or moving away.
+    }
+    let discriminant = b*b - a*c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let t = (-b - discriminant.sqrt())/a;
+    if t <= 1.0 {Some(t)} else {None}
+}
+
+//Keeps track of which stars the camera is inside so entering and leaving each is reported once.
+#[derive(Default)]
+pub struct Contacts {
+    inside: Vec<StarId>,
+    events: VecDeque<StarContact>
+}
+
+impl Contacts {
+    pub fn update(&mut self, touching: Vec<StarId>) { //The stars the camera passed through or ended up in this tick.
+        for star in &touching {
+            if !self.inside.contains(star) {
+                self.push(StarContact { star: *star, entered: true });
+            }
+        }
+        let left: Vec<StarId> = self.inside.iter().filter(|star| !touching.contains(star)).copied().collect();
+        for star in left {
+            self.push(StarContact { star, entered: false });
+        }
+        self.inside = touching;
+    }
+
+    pub fn next(&mut self) -> Option<StarContact> {
+        self.events.pop_front()
+    }
+
+    fn push(&mut self, contact: StarContact) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(contact);
+    }
+}
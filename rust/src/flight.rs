@@ -0,0 +1,162 @@
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::bytes::{ByteReader, ByteWriter};
+
+pub const MAX_BETA: f32 = 0.999; //Nothing stops the camera passing the speed of light, so it's treated as just under.
+const MAX_CHUNK_FOV: f32 = 2.1; //Radians. Near the speed of light nearly everything ends up in view, far too much to load.
+
+//How the camera responds to the controls in free flight. Set with Universe::set_flight_model.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlightModel {
+    pub mass: f32, //Thrust is divided by this to get acceleration.
+    pub rpy_factor: f32, //Ticks the turn rate takes to catch up with the controls.
+    pub resistance: f32, //Drag per unit of speed. With no drag the camera coasts forever.
+    pub rotational_inertia: bool, //The turn controls apply torque instead of setting the turn rate, so spins keep going.
+    pub base_fov: f32, //Radians, at rest.
+    pub fov_speed_scale: f32, //The FOV widens by forward_speed^fov_speed_power/fov_speed_scale radians.
+    pub fov_speed_power: f32,
+    pub max_fov: f32, //Radians. The view degenerates as the FOV approaches 180 degrees.
+    pub cruise_distance: f32, //If above 0, thrust is scaled by the distance to the nearest star over this.
+    pub gravity: f32, //Pull of a one solar mass star one unit away, in units per tick squared. 0 turns gravity off.
+    pub avoidance: f32, //If above 0, the camera brakes within this many radii of a star and can't fly into one.
+    pub light_speed: f32, //If above 0, stars are aberrated and Doppler shifted at speed instead of the view widening.
+    pub assist: f32 //Drag on drift along any axis with no thrust on it. 0 turns flight assist off.
+}
+
+#[wasm_bindgen]
+impl FlightModel {
+    pub fn arcade() -> Self { //The original flight model.
+        FlightModel {
+            mass: 100.0,
+            rpy_factor: 20.0,
+            resistance: 1.0,
+            rotational_inertia: false,
+            base_fov: 90.0f32.to_radians(),
+            fov_speed_scale: 100.0,
+            fov_speed_power: 1.2,
+            max_fov: 170.0f32.to_radians(),
+            cruise_distance: 0.0,
+            gravity: 0.0,
+            avoidance: 0.0,
+            light_speed: 0.0,
+            assist: 0.0
+        }
+    }
+
+    pub fn newtonian() -> Self {
+        FlightModel {
+            resistance: 0.0,
+            rotational_inertia: true,
+            ..Self::arcade()
+        }
+    }
+
+    pub fn assisted() -> Self { //Newtonian, but drift the controls aren't asking for is cancelled out. Good for docking.
+        FlightModel {
+            assist: 20.0,
+            ..Self::newtonian()
+        }
+    }
+
+    pub fn cruise() -> Self { //Fast through empty space, slowing down near stars.
+        FlightModel {
+            cruise_distance: 64.0,
+            ..Self::arcade()
+        }
+    }
+
+    pub fn preset(name: &str) -> Result<FlightModel, String> {
+        match name {
+            "arcade" => Ok(Self::arcade()),
+            "newtonian" => Ok(Self::newtonian()),
+            "assisted" => Ok(Self::assisted()),
+            "cruise" => Ok(Self::cruise()),
+            _ => Err(format!("Unknown flight model \"{}\"", name))
+        }
+    }
+}
+
+impl Default for FlightModel {
+    fn default() -> Self {
+        Self::arcade()
+    }
+}
+
+impl FlightModel {
+    pub fn fov(&self, forward_speed: f32) -> f32 {
+        let fov = if forward_speed > 0.0 && self.light_speed <= 0.0 {
+            self.base_fov + forward_speed.powf(self.fov_speed_power)/self.fov_speed_scale
+        } else {
+            self.base_fov
+        };
+        fov.min(self.max_fov)
+    }
+
+    //The view chunks need loading for. With aberration, stars from well outside the view are squeezed into it when
+    //flying forwards. This is the angle the edge of the view comes from.
+    pub fn chunk_fov(&self, forward_speed: f32) -> f32 {
+        let fov = self.fov(forward_speed);
+        if self.light_speed <= 0.0 {
+            return fov;
+        }
+        let beta = (forward_speed/self.light_speed).clamp(0.0, MAX_BETA);
+        let edge = (fov/2.0).cos();
+        let source = ((edge - beta)/(1.0 - beta*edge)).acos();
+        (source*2.0).min(MAX_CHUNK_FOV.min(self.max_fov)).max(fov)
+    }
+
+    //Errs on models that would send the camera to infinity, like a mass or rpy_factor of 0, which are divided by.
+    pub fn check(&self) -> Result<(), String> {
+        let numbers = [self.mass, self.rpy_factor, self.resistance, self.base_fov, self.fov_speed_scale, self.fov_speed_power, self.max_fov, self.cruise_distance, self.gravity, self.avoidance, self.light_speed, self.assist];
+        if numbers.iter().any(|n| !n.is_finite()) {
+            return Err("Every number in a flight model must be finite".to_string());
+        }
+        for (name, value) in [("mass", self.mass), ("rpy_factor", self.rpy_factor), ("base_fov", self.base_fov), ("fov_speed_scale", self.fov_speed_scale), ("max_fov", self.max_fov)] {
+            if value <= 0.0 {
+                return Err(format!("A flight model's {} must be above 0", name));
+            }
+        }
+        if self.base_fov >= std::f32::consts::PI || self.max_fov >= std::f32::consts::PI {
+            return Err("A flight model's fovs must be under 180°".to_string());
+        }
+        if self.resistance < 0.0 || self.assist < 0.0 {
+            return Err("A flight model's resistance and assist can't be negative".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.put_f32(self.mass);
+        bytes.put_f32(self.rpy_factor);
+        bytes.put_f32(self.resistance);
+        bytes.push(self.rotational_inertia as u8);
+        bytes.put_f32(self.base_fov);
+        bytes.put_f32(self.fov_speed_scale);
+        bytes.put_f32(self.fov_speed_power);
+        bytes.put_f32(self.max_fov);
+        bytes.put_f32(self.cruise_distance);
+        bytes.put_f32(self.gravity);
+        bytes.put_f32(self.avoidance);
+        bytes.put_f32(self.light_speed);
+        bytes.put_f32(self.assist);
+    }
+
+    pub fn read(reader: &mut ByteReader) -> Result<Self, String> {
+        Ok(FlightModel {
+            mass: reader.f32()?,
+            rpy_factor: reader.f32()?,
+            resistance: reader.f32()?,
+            rotational_inertia: reader.u8()? != 0,
+            base_fov: reader.f32()?,
+            fov_speed_scale: reader.f32()?,
+            fov_speed_power: reader.f32()?,
+            max_fov: reader.f32()?,
+            cruise_distance: reader.f32()?,
+            gravity: reader.f32()?,
+            avoidance: reader.f32()?,
+            light_speed: reader.f32()?,
+            assist: reader.f32()?
+        })
+    }
+}
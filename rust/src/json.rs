@@ -0,0 +1,382 @@
+use std::fmt::Write;
+
+const MAX_DEPTH: usize = 64; //Far deeper than any save nests, but shallow enough that parsing can't run out of stack.
+
+//Just enough JSON for the save formats. Numbers are written with the fewest digits that read back to the same f32 or
+//f64, so nothing is lost going through text.
+#[derive(Clone, Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>) //Keeps the order the keys were written in.
+}
+
+impl Json {
+    pub fn f32(value: f32) -> Json { //Goes through the shortest f32 text so it reads back exactly.
+        Json::Number(value.to_string().parse().unwrap_or(0.0))
+    }
+
+    pub fn f32s(values: &[f32]) -> Json {
+        Json::Array(values.iter().map(|v| Json::f32(*v)).collect())
+    }
+
+    pub fn get(&self, key: &str) -> Result<&Json, String> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v).ok_or_else(|| format!("Missing \"{}\"", key)),
+            _ => Err(format!("Expected an object holding \"{}\"", key))
+        }
+    }
+
+    pub fn as_f64(&self) -> Result<f64, String> {
+        match self {
+            Json::Number(n) => Ok(*n),
+            _ => Err("Expected a number".to_string())
+        }
+    }
+
+    pub fn as_f32(&self) -> Result<f32, String> {
+        self.as_f64().map(|n| n as f32)
+    }
+
+    pub fn as_u32(&self) -> Result<u32, String> {
+        let n = self.as_f64()?;
+        if n.fract() != 0.0 || n < 0.0 || n > u32::MAX as f64 {
+            return Err(format!("Expected a whole number, not {}", n));
+        }
+        Ok(n as u32)
+    }
+
+    pub fn as_bool(&self) -> Result<bool, String> {
+        match self {
+            Json::Bool(b) => Ok(*b),
+            _ => Err("Expected true or false".to_string())
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str, String> {
+        match self {
+            Json::String(s) => Ok(s),
+            _ => Err("Expected a string".to_string())
+        }
+    }
+
+    pub fn as_f64s<const N: usize>(&self) -> Result<[f64; N], String> {
+        match self {
+            Json::Array(items) if items.len() == N => {
+                let mut values = [0.0; N];
+                for (value, item) in values.iter_mut().zip(items) {
+                    *value = item.as_f64()?;
+                }
+                Ok(values)
+            },
+            _ => Err(format!("Expected a list of {} numbers", N))
+        }
+    }
+
+    pub fn as_i32s<const N: usize>(&self) -> Result<[i32; N], String> {
+        let mut values = [0; N];
+        for (value, n) in values.iter_mut().zip(self.as_f64s::<N>()?) {
+            if n.fract() != 0.0 || n < i32::MIN as f64 || n > i32::MAX as f64 {
+                return Err(format!("Expected a whole number, not {}", n));
+            }
+            *value = n as i32;
+        }
+        Ok(values)
+    }
+
+    pub fn as_f32s<const N: usize>(&self) -> Result<[f32; N], String> {
+        Ok(self.as_f64s::<N>()?.map(|v| v as f32))
+    }
+
+    //Pretty prints with two space indents, keeping lists of numbers on one line.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        self.write(&mut text, 0);
+        text
+    }
+
+    fn write(&self, text: &mut String, indent: usize) {
+        match self {
+            Json::Null => text.push_str("null"),
+            Json::Bool(b) => text.push_str(if *b {"true"} else {"false"}),
+            Json::Number(n) if n.is_finite() => write!(text, "{}", n).unwrap(),
+            Json::Number(_) => text.push_str("null"), //JSON has no infinities.
+            Json::String(s) => write_string(text, s),
+            Json::Array(items) => {
+                text.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        text.push_str(", ");
+                    }
+                    item.write(text, indent);
+                }
+                text.push(']');
+            },
+            Json::Object(fields) => {
+                text.push('{');
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    text.push_str(if i > 0 {",\n"} else {"\n"});
+                    text.push_str(&"  ".repeat(indent + 1));
+                    write_string(text, key);
+                    text.push_str(": ");
+                    value.write(text, indent + 1);
+                }
+                if !fields.is_empty() {
+                    text.push('\n');
+                    text.push_str(&"  ".repeat(indent));
+                }
+                text.push('}');
+            }
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser { text: text.as_bytes(), pos: 0, depth: 0 };
+        let value = parser.value()?;
+        parser.skip_space();
+        if parser.pos != parser.text.len() {
+            return Err(parser.error("Unexpected text after the end"));
+        }
+        Ok(value)
+    }
+}
+
+fn write_string(text: &mut String, s: &str) {
+    text.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\t' => text.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(text, "\\u{:04x}", c as u32).unwrap(),
+            c => text.push(c)
+        }
+    }
+    text.push('"');
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+    depth: usize //How many lists and objects the parser is inside.
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{} in JSON at character {}", message, self.pos)
+    }
+
+    fn skip_space(&mut self) {
+        while self.pos < self.text.len() && self.text[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_space();
+        self.text.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("Expected '{}'", c as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        if matches!(self.peek(), Some(b'{' | b'[')) {
+            if self.depth == MAX_DEPTH {
+                return Err(self.error("Too deeply nested"));
+            }
+            self.depth += 1;
+            let value = self.container();
+            self.depth -= 1;
+            return value;
+        }
+        match self.peek() {
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.word("true", Json::Bool(true)),
+            Some(b'f') => self.word("false", Json::Bool(false)),
+            Some(b'n') => self.word("null", Json::Null),
+            Some(_) => self.number(),
+            None => Err(self.error("Unexpected end"))
+        }
+    }
+
+    fn container(&mut self) -> Result<Json, String> { //An object or list.
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = vec!();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    fields.push((key, self.value()?));
+                    if self.peek() == Some(b',') {
+                        self.pos += 1;
+                    } else {
+                        self.expect(b'}')?;
+                        return Ok(Json::Object(fields));
+                    }
+                }
+            },
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = vec!();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    if self.peek() == Some(b',') {
+                        self.pos += 1;
+                    } else {
+                        self.expect(b']')?;
+                        return Ok(Json::Array(items));
+                    }
+                }
+            },
+            _ => Err(self.error("Expected an object or list"))
+        }
+    }
+
+    fn word(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        if !self.text[self.pos..].starts_with(word.as_bytes()) {
+            return Err(self.error("Unexpected text"));
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    //Follows JSON's grammar exactly, so things Rust would parse like +1, 01, .5 and inf aren't let through. Numbers too
+    //big for an f64 aren't either.
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        self.skip(|c| c == b'-');
+        let leading = self.pos;
+        if self.skip(|c| c == b'0') == 0 {
+            self.skip(|c| c.is_ascii_digit());
+        }
+        let mut valid = self.pos > leading && (self.text[leading] != b'0' || self.pos == leading + 1);
+        if valid && self.skip(|c| c == b'.') == 1 {
+            valid = self.skip(|c| c.is_ascii_digit()) > 0;
+        }
+        if valid && self.skip(|c| matches!(c, b'e' | b'E')) == 1 {
+            self.skip(|c| matches!(c, b'+' | b'-'));
+            valid = self.skip(|c| c.is_ascii_digit()) > 0;
+        }
+        match std::str::from_utf8(&self.text[start..self.pos]).ok().and_then(|s| s.parse().ok()).filter(|n: &f64| valid && n.is_finite()) {
+            Some(n) => Ok(Json::Number(n)),
+            None => {
+                self.pos = start;
+                Err(self.error("Expected a value"))
+            }
+        }
+    }
+
+    fn skip(&mut self, matches: impl Fn(u8) -> bool) -> usize { //Moves past bytes that match, returning how many.
+        let start = self.pos;
+        while self.text.get(self.pos).is_some_and(|c| matches(*c)) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut bytes = vec!();
+        loop {
+            let Some(&c) = self.text.get(self.pos) else {
+                return Err(self.error("Unterminated string"));
+            };
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escape = self.text.get(self.pos).copied();
+                    self.pos += 1;
+                    match escape {
+                        Some(b'"') => bytes.push(b'"'),
+                        Some(b'\\') => bytes.push(b'\\'),
+                        Some(b'/') => bytes.push(b'/'),
+                        Some(b'b') => bytes.push(8),
+                        Some(b'f') => bytes.push(12),
+                        Some(b'n') => bytes.push(b'\n'),
+                        Some(b'r') => bytes.push(b'\r'),
+                        Some(b't') => bytes.push(b'\t'),
+                        Some(b'u') => {
+                            let c = self.unicode_escape()?;
+                            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                        },
+                        _ => return Err(self.error("Bad escape"))
+                    }
+                },
+                c => bytes.push(c)
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("Text that is not valid UTF-8"))
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, String> { //After the \u. Joins up surrogate pairs.
+        let first = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&first) && self.text[self.pos..].starts_with(b"\\u") {
+            self.pos += 2;
+            let second = self.hex4()?;
+            0x10000 + ((first - 0xD800) << 10) + (second.wrapping_sub(0xDC00) & 0x3FF)
+        } else {
+            first
+        };
+        char::from_u32(code).ok_or_else(|| self.error("Bad unicode escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.pos..self.pos + 4)
+            .filter(|d| d.iter().all(u8::is_ascii_hexdigit)) //from_str_radix would take a sign.
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error("Bad unicode escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = r#"{"a": [1, -2.5e3, true, null], "b": {"c": "tab\t \"quoted\" é 😀"}, "d": []}"#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(json.get("b").unwrap().get("c").unwrap().as_str().unwrap(), "tab\t \"quoted\" é 😀");
+        assert!(json.get("a").unwrap().as_f64s::<4>().is_err());
+
+        let again = Json::parse(&json.to_text()).unwrap();
+        assert_eq!(format!("{:?}", json), format!("{:?}", again));
+        assert_eq!(Json::f32(0.1).to_text(), "0.1");
+    }
+
+    #[test]
+    fn rejects_bad_json() {
+        for text in ["", "{", "[1,]", "{\"a\" 1}", "\"open", "1 2", "nope", "+1", "01", "-", "1.", ".5", "1e", "--1", "inf", "NaN", "1e999", "\"\\u+abc\""] {
+            assert!(Json::parse(text).is_err(), "{}", text);
+        }
+        for (text, n) in [("0", 0.0), ("-0.5", -0.5), ("10e-1", 1.0), ("2E+2", 200.0)] {
+            assert_eq!(Json::parse(text).unwrap().as_f64().unwrap(), n);
+        }
+        assert!(Json::parse(&"[".repeat(200000)).is_err());
+        assert!(Json::parse(&format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH))).is_ok());
+    }
+}
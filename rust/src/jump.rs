@@ -0,0 +1,58 @@
+use crate::vector3::{ChunkVector, WorldVector};
+
+const WARP_TICKS: f32 = 60.0; //Shortest spool up. It carries on until the destination has been generated.
+const ARRIVE_TICKS: f32 = 45.0;
+pub const WARP_FOV: f32 = 2.0; //Radians at the peak of the warp.
+pub const PREFETCH_CHUNKS: usize = 2048; //Destination chunks generated per tick while spooling up.
+
+pub enum JumpStep {
+    Warping,
+    Arrive, //Move the camera to the target now.
+    Done
+}
+
+//A jump widens the view while the chunks around the target are generated a few at a time, moves the camera there in
+//one go and then narrows the view back down.
+pub struct Jump {
+    pub target: WorldVector,
+    pub origin: ChunkVector, //The floating origin to use at the target.
+    start_fov: f32,
+    time: f32,
+    arrived: bool
+}
+
+impl Jump {
+    pub fn new(target: WorldVector, start_fov: f32) -> Self {
+        Jump { target, origin: ChunkVector::from_world(target), start_fov, time: 0.0, arrived: false }
+    }
+
+    pub fn arrived(&self) -> bool {
+        self.arrived
+    }
+
+    pub fn tick(&mut self, delta: f32, ready: bool) -> JumpStep { //ready is whether the destination has been generated.
+        self.time += delta;
+        if !self.arrived {
+            if self.time >= WARP_TICKS && ready {
+                self.arrived = true;
+                self.time = 0.0;
+                return JumpStep::Arrive;
+            }
+            JumpStep::Warping
+        } else if self.time >= ARRIVE_TICKS {
+            JumpStep::Done
+        } else {
+            JumpStep::Warping
+        }
+    }
+
+    pub fn fov(&self, end_fov: f32) -> f32 { //end_fov is the view to settle back to at the target.
+        let (from, to, t) = if self.arrived {
+            (WARP_FOV, end_fov, self.time/ARRIVE_TICKS)
+        } else {
+            (self.start_fov, WARP_FOV, self.time/WARP_TICKS)
+        };
+        let t = t.clamp(0.0, 1.0);
+        from + (to - from)*t*t*(3.0 - 2.0*t) //Smoothstep.
+    }
+}
@@ -0,0 +1,256 @@
+use crate::{chunk::Chunk, json::Json, overlay::{StarId, ADDED_FLAG}};
+
+//What project_stars_layout writes for each star, and in what order. The description handed to frontends has a version
+//that goes up whenever a field changes meaning, so they can check they know how to read it. Adding fields doesn't
+//change it.
+pub const LAYOUT_VERSION: u32 = 1;
+const SUN_TEMPERATURE: f32 = 5778.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Id, //Five numbers, the StarId's cx, cy and cz, 1 for stars the user added or 0 for generated ones, then the index
+        //without the added flag. Exact within about 2 billion units of home.
+    Depth, //Distance along the view direction, or straight line distance for the wide projections.
+    X, //The top left of the square to draw the star in, as project_stars gives.
+    Y,
+    Size, //The side of the square.
+    R, //Colour from the star's temperature, 0 to 1.
+    G,
+    B,
+    Brightness, //Luminosity relative to the sun, including any Doppler beaming.
+    Shift, //The Doppler shift on colour temperature, as project_stars_shifted gives.
+    Beaming, //The Doppler factor on brightness, as project_stars_shifted gives.
+    PrevX, //Where x and y were before the last tick, for drawing streaks. The same as x and y if the star was behind the
+    PrevY  //camera. Skips the star arrays.
+}
+
+const FIELDS: [Field; 13] = [Field::Id, Field::Depth, Field::X, Field::Y, Field::Size, Field::R, Field::G, Field::B, Field::Brightness, Field::Shift, Field::Beaming, Field::PrevX, Field::PrevY];
+
+//Everything a layout might want to know about a star that's been projected.
+pub struct Projected {
+    pub id: Option<StarId>, //Only looked up for layouts that need it.
+    pub scale: f32, //Alpha over the depth, as Projection::project gives. Depth is only worked out if it's asked for.
+    pub alpha: f32,
+    pub x: f32,
+    pub y: f32,
+    pub prev_x: f32,
+    pub prev_y: f32,
+    pub size: f32,
+    pub shift: f32,
+    pub beaming: f32
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    fields: Vec<Field>,
+    stride: usize,
+    needs_ids: bool,
+    needs_previous: bool
+}
+
+impl Field {
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Id => "id",
+            Field::Depth => "depth",
+            Field::X => "x",
+            Field::Y => "y",
+            Field::Size => "size",
+            Field::R => "r",
+            Field::G => "g",
+            Field::B => "b",
+            Field::Brightness => "brightness",
+            Field::Shift => "shift",
+            Field::Beaming => "beaming",
+            Field::PrevX => "prev_x",
+            Field::PrevY => "prev_y"
+        }
+    }
+
+    pub fn parts(self) -> &'static [&'static str] { //What each of its numbers is, for fields that take up more than one.
+        match self {
+            Field::Id => &["cx", "cy", "cz", "added", "index"],
+            _ => &[]
+        }
+    }
+
+    pub fn width(self) -> usize { //How many numbers it takes up.
+        self.parts().len().max(1)
+    }
+
+    fn needs_id(self) -> bool { //Colour and brightness come from the star's mass, which is found from its id.
+        matches!(self, Field::Id | Field::R | Field::G | Field::B | Field::Brightness)
+    }
+}
+
+impl Layout {
+    pub fn new(fields: Vec<Field>) -> Self {
+        let stride = fields.iter().map(|f| f.width()).sum();
+        let needs_ids = fields.iter().any(|f| f.needs_id());
+        let needs_previous = fields.iter().any(|f| matches!(f, Field::PrevX | Field::PrevY));
+        Layout { fields, stride, needs_ids, needs_previous }
+    }
+
+    //Field names separated by commas, like "x, y, size, brightness".
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut fields = vec!();
+        for name in text.split(',').map(str::trim) {
+            let field = *FIELDS.iter().find(|f| f.name() == name).ok_or_else(|| format!("Unknown star field \"{}\"", name))?;
+            if fields.contains(&field) {
+                return Err(format!("The star field \"{}\" is listed twice", name));
+            }
+            fields.push(field);
+        }
+        Ok(Layout::new(fields))
+    }
+
+    pub fn to_text(&self) -> String { //What parse takes.
+        self.fields.iter().map(|f| f.name()).collect::<Vec<_>>().join(", ")
+    }
+
+    pub fn stride(&self) -> usize { //Numbers per star.
+        self.stride
+    }
+
+    pub fn needs_ids(&self) -> bool {
+        self.needs_ids
+    }
+
+    pub fn needs_previous(&self) -> bool {
+        self.needs_previous
+    }
+
+    pub fn describe(&self) -> String {
+        let number = |n: usize| Json::Number(n as f64);
+        let mut offset = 0;
+        let fields = self.fields.iter().map(|field| {
+            let mut described = vec!(
+                ("name".to_string(), Json::String(field.name().to_string())),
+                ("offset".to_string(), number(offset)),
+                ("count".to_string(), number(field.width()))
+            );
+            if !field.parts().is_empty() {
+                let parts = field.parts().iter().map(|part| Json::String(part.to_string())).collect();
+                described.push(("parts".to_string(), Json::Array(parts)));
+            }
+            offset += field.width();
+            Json::Object(described)
+        }).collect();
+        Json::Object(vec!(
+            ("version".to_string(), number(LAYOUT_VERSION as usize)),
+            ("stride".to_string(), number(self.stride)),
+            ("fields".to_string(), Json::Array(fields))
+        )).to_text()
+    }
+
+    //out must have room for stride numbers. This runs for every star on screen, so anything to do with the star's mass
+    //is left to write_details.
+    pub fn write(&self, out: &mut [f32], star: &Projected) {
+        let mut i = 0;
+        for field in &self.fields {
+            out[i] = match field {
+                Field::Depth => star.alpha/star.scale,
+                Field::X => star.x,
+                Field::Y => star.y,
+                Field::Size => star.size,
+                Field::Shift => star.shift,
+                Field::Beaming => star.beaming,
+                Field::PrevX => star.prev_x,
+                Field::PrevY => star.prev_y,
+                _ => 0.0 //Filled in by write_details.
+            };
+            i += field.width();
+        }
+        if self.needs_ids {
+            self.write_details(out, star);
+        }
+    }
+
+    #[cold]
+    fn write_details(&self, out: &mut [f32], star: &Projected) {
+        let mass = star.id.map_or(1.0, |id| Chunk::star_mass(id.chunk(), id.index));
+        let rgb = colour(SUN_TEMPERATURE*mass.powf(0.54)*star.shift);
+        let mut i = 0;
+        for field in &self.fields {
+            match field {
+                Field::Id => {
+                    let id = star.id.unwrap_or(StarId { cx: 0, cy: 0, cz: 0, index: 0 });
+                    let added = if id.is_added() {1.0} else {0.0};
+                    out[i..i+5].copy_from_slice(&[id.cx as f32, id.cy as f32, id.cz as f32, added, (id.index & !ADDED_FLAG) as f32]);
+                },
+                Field::R => out[i] = rgb[0],
+                Field::G => out[i] = rgb[1],
+                Field::B => out[i] = rgb[2],
+                Field::Brightness => out[i] = mass.powf(3.5)*star.beaming,
+                _ => {}
+            }
+            i += field.width();
+        }
+    }
+}
+
+//The colour of a black body at a temperature in kelvin, brightest channel 1. Fitted curves good from about 1000K to
+//40000K.
+fn colour(temperature: f32) -> [f32; 3] {
+    let t = temperature.clamp(1000.0, 40000.0)/100.0;
+    let (r, g, b) = if t <= 66.0 {
+        let b = if t <= 19.0 {0.0} else {138.51773*(t - 10.0).ln() - 305.0448};
+        (255.0, 99.4708*t.ln() - 161.11957, b)
+    } else {
+        (329.69873*(t - 60.0).powf(-0.13320476), 288.12217*(t - 60.0).powf(-0.075514849), 255.0)
+    };
+    [r, g, b].map(|c: f32| (c/255.0).clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{overlay::Overlay, vector3::WorldVector};
+
+    use super::*;
+
+    #[test]
+    fn describes_and_writes_fields() {
+        let layout = Layout::parse("size, id, r, g, b, brightness").unwrap();
+        assert_eq!(layout.stride(), 10);
+        assert!(layout.needs_ids());
+        let description = Json::parse(&layout.describe()).unwrap();
+        assert_eq!(description.get("stride").unwrap().as_u32().unwrap(), 10);
+        let fields = match description.get("fields").unwrap() {
+            Json::Array(fields) => fields,
+            _ => panic!("fields should be a list")
+        };
+        assert_eq!(fields[2].get("name").unwrap().as_str().unwrap(), "r");
+        assert_eq!(fields[2].get("offset").unwrap().as_u32().unwrap(), 6);
+        assert!(fields[1].get("parts").is_ok() && fields[0].get("parts").is_err());
+
+        let id = StarId { cx: -7, cy: 3, cz: 12, index: 2 };
+        let mut out = [0.0; 10];
+        let star = |id| Projected { id: Some(id), scale: 100.0, alpha: 1000.0, x: 1.0, y: 2.0, prev_x: 1.0, prev_y: 2.0, size: 0.5, shift: 1.0, beaming: 1.0 };
+        layout.write(&mut out, &star(id));
+        assert_eq!(out[..6], [0.5, -7.0, 3.0, 12.0, 0.0, 2.0]);
+        assert!(out[6..9].iter().all(|c| (0.0..=1.0).contains(c)) && out[9] > 0.0);
+
+        assert!(Layout::parse("x, y, x").is_err());
+        assert!(Layout::parse("x, colour").is_err());
+    }
+
+    #[test]
+    fn added_ids_round_trip() {
+        let mut overlay = Overlay::new();
+        let layout = Layout::parse("id").unwrap();
+        for n in 0..3 {
+            let id = overlay.add_star(WorldVector::new(-300.0, 5.0, 1e6), String::new());
+            let mut out = [0.0; 5];
+            layout.write(&mut out, &Projected { id: Some(id), scale: 1.0, alpha: 1.0, x: 0.0, y: 0.0, prev_x: 0.0, prev_y: 0.0, size: 1.0, shift: 1.0, beaming: 1.0 });
+            let index = out[4] as u32 | if out[3] == 1.0 {ADDED_FLAG} else {0};
+            assert_eq!(StarId { cx: out[0] as i32, cy: out[1] as i32, cz: out[2] as i32, index }, id);
+            assert_eq!(index & !ADDED_FLAG, n);
+        }
+    }
+
+    #[test]
+    fn hotter_is_bluer() {
+        let (cool, hot) = (colour(3000.0), colour(20000.0));
+        assert!(cool[0] > cool[2] && hot[2] > hot[0]);
+    }
+}
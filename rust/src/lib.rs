@@ -6,9 +6,28 @@ mod universe;
 mod chunk;
 mod matrix;
 mod orientation;
+mod quaternion;
 mod camera;
 mod viewport;
 mod chunkstore;
+mod octree;
+mod overlay;
+mod stats;
+mod soa;
+mod autopilot;
+mod orbit;
+mod camera_path;
+mod bytes;
+mod recording;
+mod flight;
+mod collision;
+mod jump;
+mod location;
+mod json;
+mod session;
+mod projection;
+mod layout;
+mod sort;
 
 // #[wasm_bindgen]
 // pub fn add(a: i32, b: i32) -> i32{
@@ -20,6 +39,11 @@ pub fn new_universe(width: u32, height: u32, render_distance: f32) -> Universe{
     Universe::new(width, height, render_distance)
 }
 
+#[wasm_bindgen]
+pub fn replay_inputs(log: &[u8]) -> Result<Universe, String> { //Takes a log from Universe::stop_recording.
+    Universe::replay(log)
+}
+
 // #[wasm_bindgen]
 // extern "C" {
 //     #[wasm_bindgen(js_namespace = console)]
@@ -32,4 +56,14 @@ extern "C" {
 
     #[wasm_bindgen(static_method_of = Date)]
     pub fn now() -> f64;
-}
\ No newline at end of file
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn now_ms() -> f64 {
+    Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now_ms() -> f64 { //Date isn't available outside of the browser.
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0.0, |t| t.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,99 @@
+use std::f32::consts::{PI, TAU};
+
+use crate::{bytes::{ByteReader, ByteWriter, from_base64, to_base64}, chunk::GENERATOR_VERSION, vector3::{ChunkVector, PointVector, WorldVector, CHUNK_SIZE}};
+
+//Location codes are a version byte, the star generator version, the camera's chunk, its position within the chunk and
+//its roll, pitch and yaw as 16 bit fractions, then a CRC-16 of all that. Written out with to_base64 that's 38
+//characters, and good to about a hundredth of a unit and a hundredth of a degree.
+const VERSION: u8 = 1;
+const STEPS: f64 = 65536.0;
+
+pub struct Location {
+    pub pos: WorldVector,
+    pub rpy: PointVector //As given by Orientation::to_euler.
+}
+
+impl Location {
+    pub fn to_code(&self) -> String {
+        let chunk = ChunkVector::from_world(self.pos);
+        let offset = (self.pos - chunk.world_corner())*(STEPS/CHUNK_SIZE as f64);
+        let mut bytes = vec!(VERSION, GENERATOR_VERSION);
+        bytes.put_chunk(chunk);
+        offset.to_array().into_iter().for_each(|c| bytes.put_u16(c.round().clamp(0.0, STEPS - 1.0) as u16));
+        self.rpy.to_array().into_iter().for_each(|angle| bytes.put_u16(quantise(angle)));
+        bytes.put_u16(crc16(&bytes));
+        to_base64(&bytes)
+    }
+
+    pub fn from_code(code: &str) -> Result<Self, String> {
+        let bytes = from_base64(code.trim()).ok_or("Not a StarTest location code")?;
+        let (body, check) = bytes.split_at(bytes.len().saturating_sub(2));
+        if check.len() < 2 || crc16(body) != u16::from_le_bytes([check[0], check[1]]) {
+            return Err("Location code is damaged".to_string());
+        }
+
+        let mut reader = ByteReader::new(body, "location code");
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(format!("Unsupported location code version {}", version));
+        }
+        if reader.u8()? != GENERATOR_VERSION {
+            return Err("Location code is from a different version of the universe".to_string());
+        }
+        let chunk = reader.chunk()?;
+        let offset = WorldVector::new(reader.u16()? as f64, reader.u16()? as f64, reader.u16()? as f64);
+        let rpy = PointVector::new(unquantise(reader.u16()?), unquantise(reader.u16()?), unquantise(reader.u16()?));
+        reader.finish()?;
+        Ok(Location { pos: chunk.world_corner() + offset*(CHUNK_SIZE as f64/STEPS), rpy })
+    }
+}
+
+fn quantise(angle: f32) -> u16 { //Angles wrap around, so pi and -pi come out the same.
+    (((angle + PI)/TAU) as f64*STEPS).round().rem_euclid(STEPS) as u16
+}
+
+fn unquantise(value: u16) -> f32 {
+    (value as f64/STEPS) as f32*TAU - PI
+}
+
+fn crc16(bytes: &[u8]) -> u16 { //CRC-16/CCITT-FALSE.
+    let mut crc = 0xFFFFu16;
+    for byte in bytes {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {(crc << 1) ^ 0x1021} else {crc << 1};
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let location = Location { pos: WorldVector::new(-1.5e9, 42.125, 7.0e6), rpy: PointVector::new(3.0, -1.2, -0.4) };
+        let code = location.to_code();
+        assert_eq!(code.len(), 38);
+        assert!(code.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_'));
+
+        let back = Location::from_code(&code).unwrap();
+        for (a, b) in location.pos.to_array().into_iter().zip(back.pos.to_array()) {
+            assert!((a - b).abs() < 0.01, "{:?} != {:?}", location.pos, back.pos);
+        }
+        for (a, b) in location.rpy.to_array().into_iter().zip(back.rpy.to_array()) {
+            assert!((a - b).abs() < 1e-3, "{:?} != {:?}", location.rpy, back.rpy);
+        }
+    }
+
+    #[test]
+    fn rejects_damaged_codes() {
+        let code = Location { pos: WorldVector::new(1.0, 2.0, 3.0), rpy: PointVector::zeros() }.to_code();
+        let mut damaged = code.clone().into_bytes();
+        damaged[10] = if damaged[10] == b'A' {b'B'} else {b'A'};
+        assert!(Location::from_code(&String::from_utf8(damaged).unwrap()).is_err());
+        assert!(Location::from_code(&code[..20]).is_err());
+        assert!(Location::from_code("not a code!").is_err());
+    }
+}
@@ -20,6 +20,16 @@ impl Matrix3 {
         Matrix3 { values }
     }
 
+    pub fn from_vectors_horiz(vectors: [PointVector; 3]) -> Self {
+        let mut values = [0.0 ; 9];
+
+        for (i, vector) in vectors.iter().enumerate() {
+            values[i*3..i*3+3].copy_from_slice(&vector.to_array());
+        }
+
+        Matrix3 { values }
+    }
+
     pub fn to_vectors_vert(&self) -> [PointVector; 3] {
         let v = &self.values;
         return [
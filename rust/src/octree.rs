@@ -0,0 +1,428 @@
+use std::mem::size_of;
+
+use crate::{chunk::Chunk, vector3::{ChunkVector, PointVector, CHUNK_SIZE}};
+
+const CHUNK_F32: f32 = CHUNK_SIZE as f32;
+
+//Octree over chunk coordinates. Each leaf holds the stars of exactly one chunk, so chunks can be added and removed
+//as the ChunkStore loads and unloads them without rebuilding the whole tree. Coordinates inside the tree are relative to
+//the floating origin, so rebase has to move them when that does.
+pub struct Octree {
+    root: Option<Node>,
+    base: ChunkVector, //The floating origin.
+    origin: ChunkVector, //Lowest chunk covered by the root.
+    depth: u32 //The root covers 2^depth chunks along each axis.
+}
+
+struct Node {
+    count: usize, //Number of stars in this node and all of its children.
+    kind: NodeKind
+}
+
+enum NodeKind {
+    Branch(Box<[Option<Node>; 8]>),
+    Leaf(Vec<PointVector>)
+}
+
+impl Octree {
+    pub fn new(base: ChunkVector) -> Self {
+        Octree {
+            root: None,
+            base,
+            origin: ChunkVector::new(0, 0, 0),
+            depth: 0
+        }
+    }
+
+    pub fn insert(&mut self, chunk: &Chunk) { //The chunk must not already be in the tree.
+        if chunk.stars.is_empty() {
+            return;
+        }
+
+        let pos = chunk.pos - self.base;
+        if self.root.is_none() {
+            self.origin = pos;
+            self.depth = 0;
+        }
+
+        while !self.covers(pos) {
+            self.grow(pos);
+        }
+
+        let depth = self.depth;
+        self.root.get_or_insert_with(|| Node::empty(depth)).insert(self.origin, depth, pos, &chunk.stars);
+    }
+
+    pub fn remove(&mut self, pos: ChunkVector) {
+        let pos = pos - self.base;
+        if !self.covers(pos) {
+            return;
+        }
+
+        if let Some(root) = &mut self.root {
+            root.remove(self.origin, self.depth, pos);
+            if root.count == 0 {
+                self.root = None;
+            }
+        }
+    }
+
+    //Moves the tree to a new floating origin, shift being how far relative positions move as Chunk::rebase takes it. The
+    //shape of the tree stays the same, since whole chunks move.
+    pub fn rebase(&mut self, base: ChunkVector, shift: PointVector) {
+        self.origin = self.origin - (base - self.base);
+        self.base = base;
+        if let Some(root) = &mut self.root {
+            root.rebase(shift);
+        }
+    }
+
+    //Calls visitor for every star inside nodes whose bounds pass node_test. node_test is given the low and high world
+    //space corners of a node, so it can reject whole parts of the tree at once.
+    pub fn visit<F, G>(&self, mut node_test: F, mut visitor: G)
+    where
+        F: FnMut(PointVector, PointVector) -> bool,
+        G: FnMut(&PointVector)
+    {
+        if let Some(root) = &self.root {
+            root.visit(self.origin, self.depth, &mut node_test, &mut visitor);
+        }
+    }
+
+    pub fn for_each_in_sphere<F: FnMut(&PointVector)>(&self, center: PointVector, radius: f32, mut f: F) {
+        self.visit(
+            |lo, hi| box_sphere_dist_sq(lo, hi, center) <= radius*radius,
+            |star| {
+                if in_sphere(star, center, radius) {
+                    f(star);
+                }
+            }
+        );
+    }
+
+    pub fn ray_cast(&self, origin: PointVector, dir: PointVector, max_dist: f32, hit_radius: f32) -> Option<(PointVector, f32)> {
+        let mut best = None;
+        if let Some(root) = &self.root {
+            root.ray_cast(self.origin, self.depth, origin, dir, max_dist, hit_radius, &mut best);
+        }
+        best
+    }
+
+    pub fn memory_usage(&self) -> usize { //Approximate, in bytes.
+        size_of::<Self>() + self.root.as_ref().map_or(0, Node::memory_usage)
+    }
+
+    fn covers(&self, pos: ChunkVector) -> bool {
+        let size = 1i64 << self.depth;
+        let inside = |p: i32, o: i32| (0..size).contains(&(p as i64 - o as i64));
+        self.root.is_some() &&
+            inside(pos.x, self.origin.x) &&
+            inside(pos.y, self.origin.y) &&
+            inside(pos.z, self.origin.z)
+    }
+
+    fn grow(&mut self, toward: ChunkVector) { //Doubles the size of the root, keeping the old root as one of its children.
+        let size = 1i32 << self.depth;
+        let mut origin = self.origin;
+        let mut octant = 0;
+        if toward.x < origin.x {
+            origin.x -= size;
+            octant |= 1;
+        }
+        if toward.y < origin.y {
+            origin.y -= size;
+            octant |= 2;
+        }
+        if toward.z < origin.z {
+            origin.z -= size;
+            octant |= 4;
+        }
+
+        let mut children: Box<[Option<Node>; 8]> = Box::default();
+        let mut count = 0;
+        if let Some(old_root) = self.root.take() {
+            count = old_root.count;
+            children[octant] = Some(old_root);
+        }
+
+        self.root = Some(Node { count, kind: NodeKind::Branch(children) });
+        self.origin = origin;
+        self.depth += 1;
+    }
+}
+
+impl Node {
+    fn empty(depth: u32) -> Self {
+        let kind = if depth == 0 {
+            NodeKind::Leaf(vec!())
+        } else {
+            NodeKind::Branch(Box::default())
+        };
+        Node { count: 0, kind }
+    }
+
+    fn memory_usage(&self) -> usize {
+        size_of::<Self>() + match &self.kind {
+            NodeKind::Branch(children) => size_of::<[Option<Node>; 8]>() + children.iter().flatten().map(Node::memory_usage).sum::<usize>(),
+            NodeKind::Leaf(points) => points.capacity()*size_of::<PointVector>()
+        }
+    }
+
+    fn child_of(origin: ChunkVector, depth: u32, pos: ChunkVector) -> (usize, ChunkVector) { //Returns the octant and its origin.
+        let half = 1i32 << (depth - 1);
+        let mut octant = 0;
+        let mut child_origin = origin;
+        if pos.x >= origin.x + half {
+            octant |= 1;
+            child_origin.x += half;
+        }
+        if pos.y >= origin.y + half {
+            octant |= 2;
+            child_origin.y += half;
+        }
+        if pos.z >= origin.z + half {
+            octant |= 4;
+            child_origin.z += half;
+        }
+        (octant, child_origin)
+    }
+
+    fn octant_origin(origin: ChunkVector, depth: u32, octant: usize) -> ChunkVector {
+        let half = 1i32 << (depth - 1);
+        ChunkVector::new(
+            origin.x + if octant & 1 != 0 {half} else {0},
+            origin.y + if octant & 2 != 0 {half} else {0},
+            origin.z + if octant & 4 != 0 {half} else {0}
+        )
+    }
+
+    fn bounds(origin: ChunkVector, depth: u32) -> (PointVector, PointVector) {
+        let size = (1i64 << depth) as f32 * CHUNK_F32;
+        let lo = origin.corner();
+        let hi = lo + PointVector::new(size, size, size);
+        (lo, hi)
+    }
+
+    fn insert(&mut self, origin: ChunkVector, depth: u32, pos: ChunkVector, stars: &[PointVector]) {
+        self.count += stars.len();
+        match &mut self.kind {
+            NodeKind::Leaf(points) => points.extend_from_slice(stars),
+            NodeKind::Branch(children) => {
+                let (octant, child_origin) = Self::child_of(origin, depth, pos);
+                children[octant]
+                    .get_or_insert_with(|| Node::empty(depth - 1))
+                    .insert(child_origin, depth - 1, pos, stars);
+            }
+        }
+    }
+
+    fn rebase(&mut self, shift: PointVector) {
+        match &mut self.kind {
+            NodeKind::Leaf(points) => points.iter_mut().for_each(|point| *point = *point - shift),
+            NodeKind::Branch(children) => children.iter_mut().flatten().for_each(|child| child.rebase(shift))
+        }
+    }
+
+    fn remove(&mut self, origin: ChunkVector, depth: u32, pos: ChunkVector) -> usize { //Returns the number of stars removed.
+        let removed = match &mut self.kind {
+            NodeKind::Leaf(points) => {
+                let removed = points.len();
+                points.clear();
+                removed
+            },
+            NodeKind::Branch(children) => {
+                let (octant, child_origin) = Self::child_of(origin, depth, pos);
+                match &mut children[octant] {
+                    Some(child) => {
+                        let removed = child.remove(child_origin, depth - 1, pos);
+                        if child.count == 0 { //Prune empty branches so queries don't have to walk them.
+                            children[octant] = None;
+                        }
+                        removed
+                    },
+                    None => 0
+                }
+            }
+        };
+        self.count -= removed;
+        removed
+    }
+
+    fn visit<F, G>(&self, origin: ChunkVector, depth: u32, node_test: &mut F, visitor: &mut G)
+    where
+        F: FnMut(PointVector, PointVector) -> bool,
+        G: FnMut(&PointVector)
+    {
+        let (lo, hi) = Self::bounds(origin, depth);
+        if !node_test(lo, hi) {
+            return;
+        }
+
+        match &self.kind {
+            NodeKind::Leaf(points) => points.iter().for_each(visitor),
+            NodeKind::Branch(children) => {
+                for (octant, child) in children.iter().enumerate() {
+                    if let Some(child) = child {
+                        child.visit(Self::octant_origin(origin, depth, octant), depth - 1, node_test, visitor);
+                    }
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn ray_cast(
+        &self,
+        origin: ChunkVector,
+        depth: u32,
+        ray_origin: PointVector,
+        dir: PointVector,
+        max_dist: f32,
+        hit_radius: f32,
+        best: &mut Option<(PointVector, f32)>
+    ) {
+        let (lo, hi) = Self::bounds(origin, depth);
+        let padding = PointVector::new(hit_radius, hit_radius, hit_radius);
+        let limit = best.map_or(max_dist, |(_, dist)| dist);
+        match ray_box_entry(ray_origin, dir, lo - padding, hi + padding) {
+            Some(entry) if entry <= limit => {},
+            _ => return
+        }
+
+        match &self.kind {
+            NodeKind::Leaf(points) => {
+                for star in points {
+                    if let Some(dist) = ray_hit(ray_origin, dir, star, hit_radius) {
+                        if dist <= best.map_or(max_dist, |(_, d)| d) {
+                            *best = Some((*star, dist));
+                        }
+                    }
+                }
+            },
+            NodeKind::Branch(children) => {
+                for (octant, child) in children.iter().enumerate() {
+                    if let Some(child) = child {
+                        let child_origin = Self::octant_origin(origin, depth, octant);
+                        child.ray_cast(child_origin, depth - 1, ray_origin, dir, max_dist, hit_radius, best);
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn in_sphere(point: &PointVector, center: PointVector, radius: f32) -> bool {
+    let d = *point - center;
+    d*d <= radius*radius
+}
+
+//Returns the distance along the ray (dir must be normalised) at which it passes within hit_radius of the point.
+pub fn ray_hit(origin: PointVector, dir: PointVector, point: &PointVector, hit_radius: f32) -> Option<f32> {
+    let to_point = *point - origin;
+    let along = to_point*dir;
+    if along < 0.0 {
+        return None;
+    }
+    let miss_sq = to_point*to_point - along*along;
+    if miss_sq <= hit_radius*hit_radius {
+        Some(along)
+    } else {
+        None
+    }
+}
+
+fn box_sphere_dist_sq(lo: PointVector, hi: PointVector, center: PointVector) -> f32 {
+    let axis = |c: f32, l: f32, h: f32| if c < l {l - c} else if c > h {c - h} else {0.0};
+    let d = PointVector::new(
+        axis(center.x, lo.x, hi.x),
+        axis(center.y, lo.y, hi.y),
+        axis(center.z, lo.z, hi.z)
+    );
+    d*d
+}
+
+fn ray_box_entry(origin: PointVector, dir: PointVector, lo: PointVector, hi: PointVector) -> Option<f32> { //Slab test.
+    let mut t_min = 0.0f32;
+    let mut t_max = f32::INFINITY;
+    for ((o, d), (l, h)) in origin.to_array().into_iter().zip(dir.to_array())
+        .zip(lo.to_array().into_iter().zip(hi.to_array()))
+    {
+        if d == 0.0 {
+            if o < l || o > h {
+                return None;
+            }
+        } else {
+            let (a, b) = ((l - o)/d, (h - o)/d);
+            t_min = t_min.max(a.min(b));
+            t_max = t_max.min(a.max(b));
+        }
+    }
+
+    if t_min <= t_max {
+        Some(t_min)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, prelude::StdRng, Rng};
+
+    use super::*;
+    use crate::{camera::{Camera, Frustum, Projector}, chunkstore::ChunkStore, orientation::Orientation, overlay::Overlay, projection::Rectilinear};
+
+    fn sorted(stars: Vec<PointVector>) -> Vec<[u32; 3]> {
+        let mut stars: Vec<_> = stars.iter().map(|star| star.to_array().map(f32::to_bits)).collect();
+        stars.sort();
+        stars
+    }
+
+    #[test]
+    fn finds_what_a_linear_scan_does() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut camera = Camera::new(1920.0, 1.2, 1536.0);
+        let mut store = ChunkStore::start(&camera, ChunkVector::new(0, 0, 0), Overlay::new());
+        store.set_indexed(true);
+        let (width, height) = (1920, 1080);
+
+        for step in 0..12 { //Turns and moves so chunks load and unload, rebasing every few steps.
+            camera.ori = Orientation::new(PointVector::new(rng.gen_range(-3.0..3.0), rng.gen_range(-1.5..1.5), rng.gen_range(-3.0..3.0)));
+            camera.pos += camera.ori.get_mat().to_vectors_vert()[2]*rng.gen_range(0.0..900.0);
+            if step % 3 == 2 {
+                let shift = store.rebase(store.chunk_of(camera.pos));
+                camera.pos = camera.pos - shift;
+            }
+            store.update(&camera);
+            let index = store.index().unwrap();
+
+            for _ in 0..20 {
+                let center = camera.pos + PointVector::new(rng.gen_range(-800.0..800.0), rng.gen_range(-800.0..800.0), rng.gen_range(-800.0..800.0));
+                let radius = rng.gen_range(10.0..400.0);
+                let (mut indexed, mut linear) = (vec!(), vec!());
+                index.for_each_in_sphere(center, radius, |star| indexed.push(*star));
+                store.iter().filter(|star| in_sphere(star, center, radius)).for_each(|star| linear.push(*star));
+                assert_eq!(sorted(indexed), sorted(linear));
+
+                let dir = (center - camera.pos).normalised();
+                let hit = store.iter()
+                    .filter_map(|star| ray_hit(camera.pos, dir, star, radius/10.0).map(|dist| (*star, dist)))
+                    .filter(|(_, dist)| *dist <= 2000.0)
+                    .min_by(|a, b| a.1.total_cmp(&b.1));
+                let bits = |hit: Option<(PointVector, f32)>| hit.map(|(star, dist)| (star.to_array().map(f32::to_bits), dist.to_bits()));
+                assert_eq!(bits(index.ray_cast(camera.pos, dir, 2000.0, radius/10.0)), bits(hit));
+            }
+
+            let projector = Projector::new(&camera, Rectilinear::new(&camera.rvp));
+            let max_dist = camera.cvp.get_alpha()/camera.rvp.get_alpha(); //As Universe::project_stars draws them.
+            let on_screen = |star: &PointVector| projector.project_point(star)
+                .is_some_and(|p| 1.0/p.x < max_dist && p.y.abs() < width as f32/2.0 && p.z.abs() < height as f32/2.0);
+            let frustum = Frustum::new(&camera, width, height);
+            let mut culled = vec!();
+            index.visit(|lo, hi| frustum.intersects_box(lo, hi), |star| if on_screen(star) {culled.push(*star)});
+            let linear: Vec<_> = store.iter().filter(|star| on_screen(star)).copied().collect();
+            assert!(!linear.is_empty());
+            assert_eq!(sorted(culled), sorted(linear));
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::f32::consts::FRAC_PI_2;
+
+use crate::{camera::Camera, orientation::Orientation, vector3::{PointVector, WorldVector}};
+
+const TRANSITION_TICKS: f32 = 60.0; //How long it takes to swing from free flight into the orbit.
+const MAX_ELEVATION: f32 = FRAC_PI_2 - 0.01; //Stop short of the poles where "up" stops making sense.
+const MIN_RADIUS: f32 = 1.0;
+const MAX_RADIUS: f32 = 100_000.0;
+
+//Keeps the camera on a sphere around a point, looking at it. Azimuth and elevation are in radians, with azimuth 0 on
+//the +Z side of the point and positive elevation above it.
+pub struct Orbit {
+    pub center: WorldVector,
+    pub azimuth: f32,
+    pub elevation: f32,
+    pub radius: f32,
+    pub rates: PointVector, //Azimuth, elevation and zoom rates. Zoom is the fraction of the radius lost per tick.
+    pub target_rates: PointVector,
+    start_pos: WorldVector, //Free flight pose to blend from while transitioning in.
+    start_ori: Orientation,
+    transition: f32 //0 to 1.
+}
+
+impl Orbit {
+    //center is relative to the floating origin, and world_center is the same point in world coordinates.
+    pub fn new(camera: &Camera, center: PointVector, world_center: WorldVector, start_pos: WorldVector, radius: f32) -> Self {
+        let offset = camera.pos - center;
+        let flat = (offset.x*offset.x + offset.z*offset.z).sqrt();
+        let (azimuth, elevation) = if offset.magnitude() == 0.0 {
+            (0.0, 0.0)
+        } else {
+            (offset.x.atan2(offset.z), offset.y.atan2(flat).clamp(-MAX_ELEVATION, MAX_ELEVATION))
+        };
+
+        Orbit {
+            center: world_center,
+            azimuth,
+            elevation,
+            radius: radius.clamp(MIN_RADIUS, MAX_RADIUS),
+            rates: PointVector::zeros(),
+            target_rates: PointVector::zeros(),
+            start_pos,
+            start_ori: camera.ori.clone(),
+            transition: 0.0
+        }
+    }
+
+    //Moves the camera for this tick in place of Camera::tick. center and start_pos are relative to the floating origin.
+    pub fn tick(&mut self, camera: &mut Camera, center: PointVector, start_pos: PointVector, delta: f32) {
+        self.rates += (self.target_rates - self.rates)*(delta/camera.flight.rpy_factor);
+        self.azimuth += self.rates.x*delta;
+        self.elevation = (self.elevation + self.rates.y*delta).clamp(-MAX_ELEVATION, MAX_ELEVATION);
+        self.radius = (self.radius*(-self.rates.z*delta).exp()).clamp(MIN_RADIUS, MAX_RADIUS);
+
+        let offset = PointVector::new(
+            self.elevation.cos()*self.azimuth.sin(),
+            self.elevation.sin(),
+            self.elevation.cos()*self.azimuth.cos()
+        );
+        let mut pos = center + offset*self.radius;
+        let mut ori = Orientation::look_at(-offset, PointVector::new(0.0, 1.0, 0.0));
+
+        if self.transition < 1.0 {
+            self.transition = (self.transition + delta/TRANSITION_TICKS).min(1.0);
+            let t = self.transition*self.transition*(3.0 - 2.0*self.transition); //Smoothstep.
+            pos = start_pos + (pos - start_pos)*t;
+            ori = self.start_ori.slerp(&ori, t);
+        }
+
+        if delta > 0.0 {
+            camera.vel = (pos - camera.pos)/delta; //Carried over when going back to free flight.
+        }
+        camera.pos = pos;
+        camera.ori = ori;
+        camera.rpy_vel = PointVector::zeros();
+    }
+
+    pub fn start_pos(&self) -> WorldVector {
+        self.start_pos
+    }
+}
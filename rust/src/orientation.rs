@@ -1,48 +1,145 @@
-use crate::{vector3::PointVector, matrix::Matrix3};
+use crate::{vector3::PointVector, matrix::Matrix3, quaternion::Quaternion};
 
+#[derive(Clone)]
 pub struct Orientation {
-    mat: Matrix3, //Rotation matrix.
+    quat: Quaternion, //Unit quaternion. Renormalised after every rotation so the basis can't drift out of shape.
+    mat: Matrix3, //Rotation matrix, derived from quat.
 }
 
 impl Orientation {
     pub fn new(rpy: PointVector) -> Self {
+        Self::from_quaternion(Self::gen_rotation(&rpy))
+    }
+
+    pub fn from_quaternion(quat: Quaternion) -> Self {
+        let quat = quat.normalised();
         Orientation {
-            mat: Self::gen_rotation_matrix(&rpy)
+            quat,
+            mat: quat.to_matrix()
         }
     }
 
-    fn gen_rotation_matrix(rpy: &PointVector) -> Matrix3{
-        let roll = [rpy.x, rpy.x.sin(), rpy.x.cos()];
-        let pitch = [rpy.y, rpy.y.sin(), rpy.y.cos()];
-        let yaw = [rpy.z, rpy.z.sin(), rpy.z.cos()];
-        
-        let yaw_mat = Matrix3{values:[
-            yaw[2], 0.0, yaw[1],
-            0.0, 1.0, 0.0,
-            -yaw[1], 0.0, yaw[2]
-        ]};
+    fn gen_rotation(rpy: &PointVector) -> Quaternion { //Yaw about Y, then pitch about X, then roll about Z.
+        let yaw = Quaternion::from_axis_angle(PointVector::new(0.0, 1.0, 0.0), rpy.z);
+        let pitch = Quaternion::from_axis_angle(PointVector::new(1.0, 0.0, 0.0), rpy.y);
+        let roll = Quaternion::from_axis_angle(PointVector::new(0.0, 0.0, 1.0), rpy.x);
 
-        let pitch_mat = Matrix3{values:[
-            1.0, 0.0, 0.0,
-            0.0, pitch[2], -pitch[1],
-            0.0, pitch[1], pitch[2]
-        ]};
+        yaw * pitch * roll
+    }
 
+    pub fn rotate(&mut self, rpy: PointVector){
+        *self = Self::from_quaternion(self.quat * Self::gen_rotation(&rpy));
+    }
 
-        let roll_mat = Matrix3{values:[
-            roll[2], -roll[1], 0.0,
-            roll[1], roll[2], 0.0,
-            0.0, 0.0, 1.0
-        ]};
+    //Points Z along forward with Y as close to up as possible. X is right, Y is up and Z is forward, like the camera.
+    pub fn look_at(forward: PointVector, up: PointVector) -> Self {
+        let z = forward.normalised();
+        let mut x = up.cross(z).normalised();
+        if x.magnitude() == 0.0 { //Looking straight along up, so any right vector will do.
+            x = PointVector::new(0.0, 0.0, 1.0).cross(z).normalised();
+            if x.magnitude() == 0.0 {
+                x = PointVector::new(1.0, 0.0, 0.0);
+            }
+        }
+        let y = z.cross(x);
+        Self::from_quaternion(Quaternion::from_matrix(&Matrix3::from_vectors_vert([x, y, z])))
+    }
 
-        return yaw_mat * pitch_mat * roll_mat;
+    //Roll, pitch and yaw that new would turn back into this orientation. Pitch is kept within +-90 degrees, and when
+    //looking straight up or down the roll is folded into the yaw.
+    pub fn to_euler(&self) -> PointVector {
+        let v = &self.mat.values;
+        let pitch = (-v[5]).clamp(-1.0, 1.0).asin();
+        if v[5].abs() > 1.0 - 1e-6 {
+            return PointVector::new(0.0, pitch, (-v[6]).atan2(v[0]));
+        }
+        PointVector::new(v[3].atan2(v[4]), pitch, v[2].atan2(v[8]))
     }
 
-    pub fn rotate(&mut self, rpy: PointVector){
-        self.mat = self.mat.clone() * Self::gen_rotation_matrix(&rpy);
+    pub fn slerp(&self, other: &Orientation, t: f32) -> Orientation {
+        Self::from_quaternion(self.quat.slerp(&other.quat, t))
+    }
+
+    pub fn get_quaternion(&self) -> Quaternion {
+        self.quat
     }
 
     pub fn get_mat(&self) -> &Matrix3 {
         &self.mat
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_euler_matrices() {
+        let rpy = PointVector::new(0.3, -1.1, 2.0);
+        let (sr, cr) = rpy.x.sin_cos();
+        let (sp, cp) = rpy.y.sin_cos();
+        let (sy, cy) = rpy.z.sin_cos();
+        let yaw = Matrix3 { values: [cy, 0.0, sy, 0.0, 1.0, 0.0, -sy, 0.0, cy] };
+        let pitch = Matrix3 { values: [1.0, 0.0, 0.0, 0.0, cp, -sp, 0.0, sp, cp] };
+        let roll = Matrix3 { values: [cr, -sr, 0.0, sr, cr, 0.0, 0.0, 0.0, 1.0] };
+        let expected = yaw * pitch * roll;
+
+        let ori = Orientation::new(rpy);
+        for (a, b) in ori.get_mat().values.iter().zip(expected.values) {
+            assert!((a - b).abs() < 1e-5, "{:?} != {:?}", ori.get_mat(), expected);
+        }
+    }
+
+    #[test]
+    fn look_at_round_trip() {
+        let ori = Orientation::new(PointVector::new(0.4, 1.3, -2.2));
+        let dirs = ori.get_mat().to_vectors_vert();
+        let rebuilt = Orientation::look_at(dirs[2], dirs[1]);
+        for (a, b) in ori.get_mat().values.iter().zip(rebuilt.get_mat().values) {
+            assert!((a - b).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn euler_round_trip() {
+        let rpy = PointVector::new(-2.5, 0.7, 1.9);
+        let back = Orientation::new(rpy).to_euler();
+        for (a, b) in rpy.to_array().into_iter().zip(back.to_array()) {
+            assert!((a - b).abs() < 1e-4, "{:?} != {:?}", rpy, back);
+        }
+
+        let up = Orientation::new(PointVector::new(0.5, -std::f32::consts::FRAC_PI_2, 1.0));
+        let rebuilt = Orientation::new(up.to_euler());
+        for (a, b) in up.get_mat().values.iter().zip(rebuilt.get_mat().values) {
+            assert!((a - b).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn slerp_halfway() {
+        let a = Orientation::new(PointVector::zeros());
+        let b = Orientation::new(PointVector::new(0.0, 0.0, 1.2));
+        let half = a.slerp(&b, 0.5);
+        let expected = Orientation::new(PointVector::new(0.0, 0.0, 0.6));
+        for (x, y) in half.get_mat().values.iter().zip(expected.get_mat().values) {
+            assert!((x - y).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn stays_orthonormal() {
+        let mut ori = Orientation::new(PointVector::zeros());
+        for i in 0..100_000 {
+            let t = i as f32 * 0.001;
+            ori.rotate(PointVector::new(t.sin(), t.cos(), (t*0.7).sin()) * 0.003);
+        }
+
+        let dirs = ori.get_mat().to_vectors_vert();
+        for i in 0..3 {
+            for j in 0..3 {
+                let expected = if i == j {1.0} else {0.0};
+                assert!((dirs[i]*dirs[j] - expected).abs() < 1e-5, "basis skewed: {:?}", dirs);
+            }
+        }
+    }
+}
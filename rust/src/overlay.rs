@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{bytes::{ByteReader, ByteWriter}, chunk::Chunk, vector3::{ChunkVector, PointVector, WorldVector}};
+
+const MAGIC: &[u8; 4] = b"STOV";
+const VERSION: u8 = 2;
+pub const ADDED_FLAG: u32 = 1 << 31; //Set on the index of stars added by the user, clear for generated ones.
+
+//Identifies a star by its chunk and its index in that chunk. Generated stars are numbered in the order Chunk::populate
+//makes them, so the same id always refers to the same star.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StarId {
+    pub cx: i32,
+    pub cy: i32,
+    pub cz: i32,
+    pub index: u32
+}
+
+#[derive(Default)]
+struct ChunkDelta {
+    next_added: u32,
+    removed: Vec<u32>,
+    moved: Vec<(u32, PointVector)>, //Positions are offsets from the chunk's corner.
+    added: Vec<(u32, PointVector)>,
+    metadata: Vec<(u32, String)>
+}
+
+//User edits layered on top of the procedural universe. Applied to every chunk as it's generated, so the edits survive
+//the chunk being unloaded and loaded again.
+#[derive(Default)]
+pub struct Overlay {
+    deltas: HashMap<ChunkVector, ChunkDelta>
+}
+
+impl StarId {
+    pub fn new(chunk: ChunkVector, index: u32) -> Self {
+        StarId { cx: chunk.x, cy: chunk.y, cz: chunk.z, index }
+    }
+
+    pub fn chunk(&self) -> ChunkVector {
+        ChunkVector::new(self.cx, self.cy, self.cz)
+    }
+
+    pub fn is_added(&self) -> bool {
+        self.index & ADDED_FLAG != 0
+    }
+}
+
+impl ChunkDelta {
+    fn is_empty(&self) -> bool { //Chunks that have ever had stars added are kept so their ids are never reused.
+        self.next_added == 0 && self.removed.is_empty() && self.moved.is_empty() && self.added.is_empty() && self.metadata.is_empty()
+    }
+
+    fn set_metadata(&mut self, index: u32, metadata: String) {
+        self.metadata.retain(|(i, _)| *i != index);
+        if !metadata.is_empty() {
+            self.metadata.push((index, metadata));
+        }
+    }
+
+    fn take_metadata(&mut self, index: u32) -> String {
+        match self.metadata.iter().position(|(i, _)| *i == index) {
+            Some(pos) => self.metadata.swap_remove(pos).1,
+            None => String::new()
+        }
+    }
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply(&self, chunk: &mut Chunk, origin: ChunkVector) {
+        let Some(delta) = self.deltas.get(&chunk.pos) else {
+            return;
+        };
+        let corner = (chunk.pos - origin).corner();
+
+        let mut i = 0;
+        while i < chunk.ids.len() {
+            let id = chunk.ids[i];
+            if delta.removed.contains(&id) {
+                chunk.ids.remove(i);
+                chunk.stars.remove(i);
+                continue;
+            }
+            if let Some((_, pos)) = delta.moved.iter().find(|(moved, _)| *moved == id) {
+                chunk.stars[i] = corner + *pos;
+            }
+            i += 1;
+        }
+
+        for (id, pos) in &delta.added {
+            chunk.ids.push(*id);
+            chunk.stars.push(corner + *pos);
+        }
+    }
+
+    pub fn exists(&self, id: &StarId) -> bool {
+        let delta = self.deltas.get(&id.chunk());
+        if id.is_added() {
+            delta.is_some_and(|delta| delta.added.iter().any(|(i, _)| *i == id.index))
+        } else {
+            delta.is_none_or(|delta| !delta.removed.contains(&id.index)) &&
+                (id.index as usize) < Chunk::populate(id.chunk(), id.chunk()).stars.len()
+        }
+    }
+
+    pub fn add_star(&mut self, pos: WorldVector, metadata: String) -> StarId {
+        let (chunk, offset) = Self::split(pos);
+        let delta = self.deltas.entry(chunk).or_default();
+        let index = delta.next_added | ADDED_FLAG;
+        delta.next_added += 1;
+        delta.added.push((index, offset));
+        delta.set_metadata(index, metadata);
+        StarId::new(chunk, index)
+    }
+
+    pub fn remove_star(&mut self, id: &StarId) -> bool {
+        self.take_star(id).is_some()
+    }
+
+    pub fn move_star(&mut self, id: &StarId, pos: WorldVector) -> Option<StarId> { //Stars moved into another chunk get a new id.
+        if !self.exists(id) {
+            return None;
+        }
+
+        let (chunk, offset) = Self::split(pos);
+        if chunk != id.chunk() {
+            let metadata = self.take_star(id)?;
+            return Some(self.add_star(pos, metadata));
+        }
+
+        let delta = self.deltas.entry(id.chunk()).or_default();
+        let list = if id.is_added() {&mut delta.added} else {&mut delta.moved};
+        list.retain(|(i, _)| *i != id.index);
+        list.push((id.index, offset));
+        Some(*id)
+    }
+
+    pub fn set_metadata(&mut self, id: &StarId, metadata: String) -> bool {
+        if !self.exists(id) {
+            return false;
+        }
+        self.deltas.entry(id.chunk()).or_default().set_metadata(id.index, metadata);
+        self.prune(id.chunk());
+        true
+    }
+
+    pub fn get_metadata(&self, id: &StarId) -> Option<&str> {
+        self.deltas.get(&id.chunk())?
+            .metadata.iter()
+            .find(|(i, _)| *i == id.index)
+            .map(|(_, metadata)| metadata.as_str())
+    }
+
+    fn split(pos: WorldVector) -> (ChunkVector, PointVector) { //Splits a position into its chunk and the offset within it.
+        let chunk = ChunkVector::from_world(pos);
+        (chunk, (pos - chunk.world_corner()).to_point())
+    }
+
+    fn take_star(&mut self, id: &StarId) -> Option<String> { //Removes a star, returning its metadata.
+        if !self.exists(id) {
+            return None;
+        }
+
+        let delta = self.deltas.entry(id.chunk()).or_default();
+        let metadata = delta.take_metadata(id.index);
+        if id.is_added() {
+            delta.added.retain(|(i, _)| *i != id.index);
+        } else {
+            delta.moved.retain(|(i, _)| *i != id.index);
+            delta.removed.push(id.index);
+        }
+        self.prune(id.chunk());
+        Some(metadata)
+    }
+
+    fn prune(&mut self, chunk: ChunkVector) {
+        if self.deltas.get(&chunk).is_some_and(|delta| delta.is_empty()) {
+            self.deltas.remove(&chunk);
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.put_header(MAGIC, VERSION);
+
+        let mut chunks: Vec<_> = self.deltas.iter().collect();
+        chunks.sort_by_key(|(pos, _)| pos.to_array());
+
+        bytes.put_u32(chunks.len() as u32);
+        for (pos, delta) in chunks {
+            bytes.put_chunk(*pos);
+            bytes.put_u32(delta.next_added);
+
+            bytes.put_u32(delta.removed.len() as u32);
+            delta.removed.iter().for_each(|i| bytes.put_u32(*i));
+
+            for list in [&delta.moved, &delta.added] {
+                bytes.put_u32(list.len() as u32);
+                for (i, star) in list {
+                    bytes.put_u32(*i);
+                    bytes.put_point(*star);
+                }
+            }
+
+            bytes.put_u32(delta.metadata.len() as u32);
+            for (i, metadata) in &delta.metadata {
+                bytes.put_u32(*i);
+                bytes.put_str(metadata);
+            }
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = ByteReader::new(bytes, "overlay");
+        let version = reader.header(MAGIC)?;
+        if version != VERSION {
+            return Err(format!("Unsupported overlay version {}", version));
+        }
+
+        let mut deltas = HashMap::new();
+        for _ in 0..reader.u32()? {
+            let pos = reader.chunk()?;
+            let mut delta = ChunkDelta { next_added: reader.u32()?, ..Default::default() };
+
+            for _ in 0..reader.u32()? {
+                delta.removed.push(reader.u32()?);
+            }
+            for list in [&mut delta.moved, &mut delta.added] {
+                for _ in 0..reader.u32()? {
+                    let index = reader.u32()?;
+                    list.push((index, reader.point()?));
+                }
+            }
+            for _ in 0..reader.u32()? {
+                let index = reader.u32()?;
+                delta.metadata.push((index, reader.string()?));
+            }
+
+            deltas.insert(pos, delta);
+        }
+
+        reader.finish()?;
+        Ok(Overlay { deltas })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{camera::Camera, chunkstore::ChunkStore};
+
+    #[test]
+    fn edits_survive_unloading_and_saving() {
+        let mut camera = Camera::new(800.0, 1.2, 1536.0);
+        let mut store = ChunkStore::start(&camera, ChunkVector::new(0, 0, 0), Overlay::new());
+        let mut generated = vec!();
+        store.for_each_star(|id, _| generated.push(id));
+        let chunk = generated.iter().map(StarId::chunk).find(|c| generated.iter().filter(|id| id.chunk() == *c).count() >= 2).unwrap();
+        let (removed, moved) = (StarId::new(chunk, 0), StarId::new(chunk, 1));
+
+        let corner = chunk.world_corner();
+        let overlay = store.overlay_mut();
+        let added = overlay.add_star(corner + WorldVector::new(1.0, 2.0, 3.0), "added".to_string());
+        assert!(overlay.remove_star(&removed));
+        assert_eq!(overlay.move_star(&moved, corner + WorldVector::new(64.0, 64.0, 64.0)), Some(moved));
+        assert!(overlay.set_metadata(&moved, "moved".to_string()));
+        store.refresh_chunk(chunk);
+
+        let check = |store: &ChunkStore| {
+            assert!(store.get_chunk(chunk).is_some());
+            let world = |id: &StarId| store.star_position(id).map(|star| store.to_world(star).to_array());
+            assert_eq!(world(&removed), None);
+            assert_eq!(world(&added), Some((corner + WorldVector::new(1.0, 2.0, 3.0)).to_array()));
+            assert_eq!(world(&moved), Some((corner + WorldVector::new(64.0, 64.0, 64.0)).to_array()));
+            assert_eq!(store.overlay().get_metadata(&added), Some("added"));
+            assert_eq!(store.overlay().get_metadata(&moved), Some("moved"));
+        };
+        check(&store);
+
+        camera.pos = PointVector::new(0.0, 0.0, -1e5);
+        store.update(&camera);
+        assert!(store.get_chunk(chunk).is_none());
+        camera.pos = PointVector::zeros();
+        store.update(&camera);
+        check(&store);
+
+        let bytes = store.overlay().to_bytes();
+        let loaded = Overlay::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.to_bytes(), bytes);
+        check(&ChunkStore::start(&camera, ChunkVector::new(0, 0, 0), loaded));
+        assert!(Overlay::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
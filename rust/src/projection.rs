@@ -0,0 +1,197 @@
+use std::f32::consts::TAU;
+
+use crate::{vector3::PointVector, viewport::Viewport};
+
+//Ways of laying the sky out on the screen. project takes a point relative to the camera, along its right, up and
+//forward directions, to how much to scale the star by and its offset in pixels from the middle of the screen with y
+//down, in that order. None if the point doesn't show. The wide projections scale stars by distance rather than depth.
+pub trait Projection {
+    fn project(&self, point: PointVector) -> Option<PointVector>;
+}
+
+//Ordinary perspective. Straight lines stay straight, but it can't show anything level with or behind the camera.
+#[derive(Clone, Copy)]
+pub struct Rectilinear {
+    alpha: f32
+}
+
+//Equidistant fisheye. How far a star is from the middle is proportional to its angle from straight ahead, so it can
+//show the whole sky, out to the ring of what's directly behind at 360°.
+#[derive(Clone, Copy)]
+pub struct Fisheye {
+    alpha: f32,
+    focal: f32, //Pixels per radian.
+    max_angle: f32
+}
+
+//The whole sky with longitude across and latitude down, as a 2:1 panorama.
+#[derive(Clone, Copy)]
+pub struct Equirectangular {
+    alpha: f32,
+    focal: f32
+}
+
+//Keeps the shapes of small things, which makes it popular for wide angle views. Can't reach what's directly behind.
+#[derive(Clone, Copy)]
+pub struct Stereographic {
+    alpha: f32,
+    focal: f32,
+    max_angle: f32
+}
+
+//Which projection to draw with. The fovs are in radians across the wider side of the screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProjectionMode {
+    Rectilinear, //Uses the camera's fov.
+    Fisheye(f32),
+    Equirectangular, //Always 360° across.
+    Stereographic(f32)
+}
+
+impl Rectilinear {
+    pub fn new(vp: &Viewport) -> Self {
+        Rectilinear { alpha: vp.get_alpha() }
+    }
+}
+
+impl Projection for Rectilinear {
+    fn project(&self, point: PointVector) -> Option<PointVector> {
+        if point.z <= 0.0 {
+            return None;
+        }
+        let scale = self.alpha/point.z;
+        Some(PointVector::new(scale, point.x*scale, -point.y*scale)) //Y coords inverted because PIXI's Y is down and mine is up.
+    }
+}
+
+impl Fisheye {
+    pub fn new(vp: &Viewport, fov: f32) -> Self {
+        Fisheye { alpha: vp.get_alpha(), focal: vp.get_maxbound()/fov, max_angle: fov/2.0 }
+    }
+}
+
+impl Projection for Fisheye {
+    fn project(&self, point: PointVector) -> Option<PointVector> {
+        let (dist, across, angle) = off_axis(point)?;
+        if angle > self.max_angle {
+            return None;
+        }
+        let r = self.focal*angle/across.max(f32::MIN_POSITIVE);
+        Some(PointVector::new(self.alpha/dist, point.x*r, -point.y*r))
+    }
+}
+
+impl Equirectangular {
+    pub fn new(vp: &Viewport) -> Self {
+        Equirectangular { alpha: vp.get_alpha(), focal: vp.get_maxbound()/TAU }
+    }
+}
+
+impl Projection for Equirectangular {
+    fn project(&self, point: PointVector) -> Option<PointVector> {
+        let dist = point.magnitude();
+        if dist == 0.0 {
+            return None;
+        }
+        let longitude = point.x.atan2(point.z);
+        let latitude = (point.y/dist).clamp(-1.0, 1.0).asin();
+        Some(PointVector::new(self.alpha/dist, longitude*self.focal, -latitude*self.focal))
+    }
+}
+
+impl Stereographic {
+    pub fn new(vp: &Viewport, fov: f32) -> Self {
+        Stereographic { alpha: vp.get_alpha(), focal: vp.get_maxbound()/(4.0*(fov/4.0).tan()), max_angle: fov/2.0 }
+    }
+}
+
+impl Projection for Stereographic {
+    fn project(&self, point: PointVector) -> Option<PointVector> {
+        let (dist, across, angle) = off_axis(point)?;
+        if angle > self.max_angle {
+            return None;
+        }
+        let r = 2.0*self.focal*(angle/2.0).tan()/across.max(f32::MIN_POSITIVE);
+        Some(PointVector::new(self.alpha/dist, point.x*r, -point.y*r))
+    }
+}
+
+//The point's distance, its distance from the line straight ahead and its angle from straight ahead.
+fn off_axis(point: PointVector) -> Option<(f32, f32, f32)> {
+    let dist = point.magnitude();
+    if dist == 0.0 {
+        return None;
+    }
+    let across = point.x.hypot(point.y);
+    Some((dist, across, across.atan2(point.z)))
+}
+
+impl ProjectionMode {
+    pub fn from_name(name: &str, fov: f32) -> Result<Self, String> {
+        let wide = |max_fov: f32| {
+            if fov > 0.0 && fov <= max_fov {
+                Ok(fov)
+            } else {
+                Err(format!("A {} projection needs a fov between 0 and {}", name, max_fov))
+            }
+        };
+        match name {
+            "rectilinear" => Ok(ProjectionMode::Rectilinear),
+            "fisheye" => Ok(ProjectionMode::Fisheye(wide(TAU)?)),
+            "equirectangular" => Ok(ProjectionMode::Equirectangular),
+            "stereographic" => Ok(ProjectionMode::Stereographic(wide(TAU*0.9)?)), //Stretches out towards infinity at 360°.
+            _ => Err(format!("Unknown projection \"{}\"", name))
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProjectionMode::Rectilinear => "rectilinear",
+            ProjectionMode::Fisheye(_) => "fisheye",
+            ProjectionMode::Equirectangular => "equirectangular",
+            ProjectionMode::Stereographic(_) => "stereographic"
+        }
+    }
+
+    pub fn fov(&self) -> f32 { //What from_name was given, or 0 for the projections that don't take one.
+        match self {
+            ProjectionMode::Fisheye(fov) | ProjectionMode::Stereographic(fov) => *fov,
+            _ => 0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    fn assert_near(p: Option<PointVector>, x: f32, y: f32) {
+        let p = p.unwrap();
+        assert!((p.y - x).abs() < 1e-2 && (p.z - y).abs() < 1e-2, "{:?} is not at {}, {}", p, x, y);
+    }
+
+    #[test]
+    fn wide_projections() {
+        let vp = Viewport::fov_maxbound(1.2, 1000.0);
+        let (ahead, right, up, behind) = (PointVector::new(0.0, 0.0, 5.0), PointVector::new(5.0, 0.0, 0.0), PointVector::new(0.0, 5.0, 0.0), PointVector::new(0.0, 0.0, -5.0));
+
+        let fisheye = Fisheye::new(&vp, PI);
+        assert_near(fisheye.project(ahead), 0.0, 0.0);
+        assert_near(fisheye.project(right), 500.0, 0.0);
+        assert_near(fisheye.project(up), 0.0, -500.0);
+        assert!(fisheye.project(behind).is_none());
+        assert!(Fisheye::new(&vp, TAU).project(behind).is_some());
+
+        let equirectangular = Equirectangular::new(&vp);
+        assert_near(equirectangular.project(right), 250.0, 0.0);
+        assert_near(equirectangular.project(up), 0.0, -250.0);
+        assert!(equirectangular.project(behind).unwrap().y.abs() > 499.0);
+
+        let stereographic = Stereographic::new(&vp, PI);
+        assert_near(stereographic.project(right), 500.0, 0.0);
+        assert!(stereographic.project(PointVector::new(1.0, 0.0, 1.0)).unwrap().y < 500.0*0.5);
+        assert!(ProjectionMode::from_name("stereographic", TAU).is_err());
+    }
+}
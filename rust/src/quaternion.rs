@@ -0,0 +1,102 @@
+use std::ops::Mul;
+
+use crate::{vector3::PointVector, matrix::Matrix3};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32
+}
+
+impl Quaternion {
+    pub fn new(w: f32, x: f32, y: f32, z: f32) -> Self {
+        Quaternion { w, x, y, z }
+    }
+
+    pub fn identity() -> Self {
+        Quaternion::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    pub fn from_axis_angle(axis: PointVector, angle: f32) -> Self { //Axis must be normalised.
+        let (s, c) = (angle/2.0).sin_cos();
+        Quaternion::new(c, axis.x*s, axis.y*s, axis.z*s)
+    }
+
+    pub fn dot(&self, other: &Quaternion) -> f32 {
+        self.w*other.w + self.x*other.x + self.y*other.y + self.z*other.z
+    }
+
+    pub fn normalised(&self) -> Quaternion {
+        let length = self.dot(self).sqrt();
+        if length == 0.0 {
+            return Quaternion::identity();
+        }
+        Quaternion::new(self.w/length, self.x/length, self.y/length, self.z/length)
+    }
+
+    pub fn to_matrix(self) -> Matrix3 {
+        let Quaternion { w, x, y, z } = self;
+        Matrix3 { values: [
+            1.0 - 2.0*(y*y + z*z), 2.0*(x*y - w*z), 2.0*(x*z + w*y),
+            2.0*(x*y + w*z), 1.0 - 2.0*(x*x + z*z), 2.0*(y*z - w*x),
+            2.0*(x*z - w*y), 2.0*(y*z + w*x), 1.0 - 2.0*(x*x + y*y)
+        ]}
+    }
+
+    pub fn from_matrix(mat: &Matrix3) -> Self { //Matrix must be a rotation.
+        let m = &mat.values;
+        let trace = m[0] + m[4] + m[8];
+        if trace > 0.0 {
+            let s = (trace + 1.0).sqrt()*2.0;
+            Quaternion::new(s/4.0, (m[7] - m[5])/s, (m[2] - m[6])/s, (m[3] - m[1])/s)
+        } else if m[0] > m[4] && m[0] > m[8] {
+            let s = (1.0 + m[0] - m[4] - m[8]).sqrt()*2.0;
+            Quaternion::new((m[7] - m[5])/s, s/4.0, (m[1] + m[3])/s, (m[2] + m[6])/s)
+        } else if m[4] > m[8] {
+            let s = (1.0 + m[4] - m[0] - m[8]).sqrt()*2.0;
+            Quaternion::new((m[2] - m[6])/s, (m[1] + m[3])/s, s/4.0, (m[5] + m[7])/s)
+        } else {
+            let s = (1.0 + m[8] - m[0] - m[4]).sqrt()*2.0;
+            Quaternion::new((m[3] - m[1])/s, (m[2] + m[6])/s, (m[5] + m[7])/s, s/4.0)
+        }.normalised()
+    }
+
+    pub fn slerp(&self, other: &Quaternion, t: f32) -> Quaternion { //Takes the shortest path between the two.
+        let mut dot = self.dot(other);
+        let mut other = *other;
+        if dot < 0.0 {
+            other = Quaternion::new(-other.w, -other.x, -other.y, -other.z);
+            dot = -dot;
+        }
+
+        let (a, b) = if dot > 0.9995 { //Nearly the same rotation, so sin(theta) is too small to divide by.
+            (1.0 - t, t)
+        } else {
+            let theta = dot.acos();
+            let sin_theta = theta.sin();
+            (((1.0 - t)*theta).sin()/sin_theta, (t*theta).sin()/sin_theta)
+        };
+
+        Quaternion::new(
+            self.w*a + other.w*b,
+            self.x*a + other.x*b,
+            self.y*a + other.y*b,
+            self.z*a + other.z*b
+        ).normalised()
+    }
+}
+
+impl Mul<Quaternion> for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, rhs: Quaternion) -> Self::Output {
+        Quaternion::new(
+            self.w*rhs.w - self.x*rhs.x - self.y*rhs.y - self.z*rhs.z,
+            self.w*rhs.x + self.x*rhs.w + self.y*rhs.z - self.z*rhs.y,
+            self.w*rhs.y - self.x*rhs.z + self.y*rhs.w + self.z*rhs.x,
+            self.w*rhs.z + self.x*rhs.y - self.y*rhs.x + self.z*rhs.w
+        )
+    }
+}
@@ -0,0 +1,226 @@
+use crate::{bytes::{ByteReader, ByteWriter}, flight::FlightModel, layout::Layout, projection::ProjectionMode, sort::DepthOrder, vector3::{ChunkVector, PointVector, WorldVector}};
+
+//Input logs start with the state the recording began from (see Universe::start_recording) followed by one event per
+//call, each a tag byte and its arguments. Runs of ticks with the same delta are stored once with a count, and control
+//calls that don't change the value are dropped, so a steady minute of flying is only a few hundred bytes.
+const MAGIC: &[u8; 4] = b"STIN";
+const VERSION: u8 = 1;
+
+const TICK: u8 = 0;
+const ROLL: u8 = 1;
+const PITCH: u8 = 2;
+const YAW: u8 = 3;
+const THRUST: u8 = 4;
+const RESIZE: u8 = 5;
+const FLIGHT: u8 = 6;
+const STRAFE: u8 = 7;
+const LIFT: u8 = 8;
+const POSITION: u8 = 9;
+const VELOCITY: u8 = 10;
+const EULER: u8 = 11;
+const LOOK_AT: u8 = 12;
+const PROJECTION: u8 = 13;
+const STEREO: u8 = 14;
+const DEPTH_ORDER: u8 = 15;
+const LAYOUT: u8 = 16;
+
+#[derive(Clone, Debug)]
+pub enum Input {
+    Ticks(u32, f32), //Count and delta.
+    Roll(f32),
+    Pitch(f32),
+    Yaw(f32),
+    Thrust(f32),
+    Strafe(f32),
+    Lift(f32),
+    Resize(u32, u32),
+    Flight(FlightModel),
+    Position(WorldVector),
+    Velocity(PointVector),
+    Euler(PointVector), //Roll, pitch and yaw.
+    LookAt(WorldVector, PointVector), //Point and up.
+    Projection(ProjectionMode),
+    Stereo(f32), //Eye distance.
+    DepthOrder(DepthOrder),
+    Layout(Layout)
+}
+
+pub struct StartState {
+    pub width: u32,
+    pub height: u32,
+    pub origin: ChunkVector,
+    pub camera: Vec<u8>, //From Camera::write_state.
+    pub indexed: bool,
+    pub arrays: bool,
+    pub overlay: Vec<u8>, //From Overlay::to_bytes.
+    pub view: View
+}
+
+//How the stars are drawn, which replays need to match as well as where the camera goes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct View {
+    pub projection: ProjectionMode,
+    pub stereo: f32,
+    pub depth_order: DepthOrder,
+    pub layout: Layout
+}
+
+impl View {
+    fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.put_str(self.projection.name());
+        bytes.put_f32(self.projection.fov());
+        bytes.put_f32(self.stereo);
+        bytes.put_str(self.depth_order.name());
+        bytes.put_str(&self.layout.to_text());
+    }
+
+    fn read(reader: &mut ByteReader) -> Result<Self, String> {
+        Ok(View {
+            projection: ProjectionMode::from_name(&reader.string()?, reader.f32()?)?,
+            stereo: reader.f32()?,
+            depth_order: DepthOrder::from_name(&reader.string()?)?,
+            layout: Layout::parse(&reader.string()?)?
+        })
+    }
+}
+
+pub struct InputLog {
+    pub start: StartState,
+    pub inputs: Vec<Input>,
+    controls: [Option<f32>; 6] //Last recorded roll, pitch, yaw, thrust, strafe and lift.
+}
+
+impl InputLog {
+    pub fn new(start: StartState) -> Self {
+        InputLog { start, inputs: vec!(), controls: [None; 6] }
+    }
+
+    pub fn record(&mut self, input: Input) {
+        let control = match input {
+            Input::Roll(v) => Some((0, v)),
+            Input::Pitch(v) => Some((1, v)),
+            Input::Yaw(v) => Some((2, v)),
+            Input::Thrust(v) => Some((3, v)),
+            Input::Strafe(v) => Some((4, v)),
+            Input::Lift(v) => Some((5, v)),
+            _ => None
+        };
+        if let Some((i, value)) = control {
+            if self.controls[i].map(f32::to_bits) == Some(value.to_bits()) {
+                return; //The JS sets the thrust every frame whether or not it changed.
+            }
+            self.controls[i] = Some(value);
+        }
+
+        if let (Input::Ticks(1, delta), Some(Input::Ticks(count, last))) = (&input, self.inputs.last_mut()) {
+            if last.to_bits() == delta.to_bits() && *count < u32::MAX {
+                *count += 1;
+                return;
+            }
+        }
+        self.inputs.push(input);
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.put_header(MAGIC, VERSION);
+
+        let start = &self.start;
+        bytes.put_u32(start.width);
+        bytes.put_u32(start.height);
+        bytes.put_chunk(start.origin);
+        bytes.put_u32(start.camera.len() as u32);
+        bytes.extend_from_slice(&start.camera);
+        bytes.push(start.indexed as u8 | (start.arrays as u8) << 1);
+        bytes.put_u32(start.overlay.len() as u32);
+        bytes.extend_from_slice(&start.overlay);
+        start.view.write(&mut bytes);
+
+        for input in &self.inputs {
+            match *input {
+                Input::Ticks(count, delta) => {
+                    bytes.push(TICK);
+                    bytes.put_u32(count);
+                    bytes.put_f32(delta);
+                },
+                Input::Roll(v) => {bytes.push(ROLL); bytes.put_f32(v)},
+                Input::Pitch(v) => {bytes.push(PITCH); bytes.put_f32(v)},
+                Input::Yaw(v) => {bytes.push(YAW); bytes.put_f32(v)},
+                Input::Thrust(v) => {bytes.push(THRUST); bytes.put_f32(v)},
+                Input::Strafe(v) => {bytes.push(STRAFE); bytes.put_f32(v)},
+                Input::Lift(v) => {bytes.push(LIFT); bytes.put_f32(v)},
+                Input::Resize(width, height) => {
+                    bytes.push(RESIZE);
+                    bytes.put_u32(width);
+                    bytes.put_u32(height);
+                },
+                Input::Flight(model) => {
+                    bytes.push(FLIGHT);
+                    model.write(&mut bytes);
+                },
+                Input::Position(pos) => {bytes.push(POSITION); bytes.put_world(pos)},
+                Input::Velocity(vel) => {bytes.push(VELOCITY); bytes.put_point(vel)},
+                Input::Euler(rpy) => {bytes.push(EULER); bytes.put_point(rpy)},
+                Input::LookAt(point, up) => {
+                    bytes.push(LOOK_AT);
+                    bytes.put_world(point);
+                    bytes.put_point(up);
+                },
+                Input::Projection(mode) => {
+                    bytes.push(PROJECTION);
+                    bytes.put_str(mode.name());
+                    bytes.put_f32(mode.fov());
+                },
+                Input::Stereo(distance) => {bytes.push(STEREO); bytes.put_f32(distance)},
+                Input::DepthOrder(order) => {bytes.push(DEPTH_ORDER); bytes.put_str(order.name())},
+                Input::Layout(ref layout) => {bytes.push(LAYOUT); bytes.put_str(&layout.to_text())}
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = ByteReader::new(bytes, "input log");
+        let version = reader.header(MAGIC)?;
+        if version != VERSION {
+            return Err(format!("Unsupported input log version {}", version));
+        }
+
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        let origin = reader.chunk()?;
+        let camera_len = reader.u32()? as usize;
+        let camera = reader.take(camera_len)?.to_vec();
+        let flags = reader.u8()?;
+        let overlay_len = reader.u32()? as usize;
+        let overlay = reader.take(overlay_len)?.to_vec();
+        let view = View::read(&mut reader)?;
+        let start = StartState { width, height, origin, camera, indexed: flags & 1 != 0, arrays: flags & 2 != 0, overlay, view };
+
+        let mut log = InputLog::new(start);
+        while !reader.is_empty() {
+            let input = match reader.u8()? {
+                TICK => Input::Ticks(reader.u32()?, reader.f32()?),
+                ROLL => Input::Roll(reader.f32()?),
+                PITCH => Input::Pitch(reader.f32()?),
+                YAW => Input::Yaw(reader.f32()?),
+                THRUST => Input::Thrust(reader.f32()?),
+                STRAFE => Input::Strafe(reader.f32()?),
+                LIFT => Input::Lift(reader.f32()?),
+                RESIZE => Input::Resize(reader.u32()?, reader.u32()?),
+                FLIGHT => Input::Flight(FlightModel::read(&mut reader)?),
+                POSITION => Input::Position(reader.world()?),
+                VELOCITY => Input::Velocity(reader.point()?),
+                EULER => Input::Euler(reader.point()?),
+                LOOK_AT => Input::LookAt(reader.world()?, reader.point()?),
+                PROJECTION => Input::Projection(ProjectionMode::from_name(&reader.string()?, reader.f32()?)?),
+                STEREO => Input::Stereo(reader.f32()?),
+                DEPTH_ORDER => Input::DepthOrder(DepthOrder::from_name(&reader.string()?)?),
+                LAYOUT => Input::Layout(Layout::parse(&reader.string()?)?),
+                tag => return Err(format!("Unknown input {} in input log", tag))
+            };
+            log.inputs.push(input);
+        }
+        Ok(log)
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{bytes::{ByteReader, ByteWriter, from_base64, to_base64}, camera::Camera, camera_path::CameraPath, chunk::GENERATOR_VERSION, flight::FlightModel, json::Json, orientation::Orientation, quaternion::Quaternion, vector3::{ChunkVector, PointVector, WorldVector}, viewport::Viewport};
+
+//Everything needed to carry on where the user left off. Chunks aren't saved, they're generated again around the camera.
+//Autopilot, orbits, jumps and recordings are left out, and so is camera path playback, though the keyframes are kept.
+//
+//The binary format is a header, the star generator version, then the fields in order with the camera in
+//Camera::write_state's layout. The JSON format has the same fields by name, with world positions for the camera and
+//the overlay in base64.
+const MAGIC: &[u8; 4] = b"STSS";
+const VERSION: u8 = 1;
+const JSON_FORMAT: &str = "startest-session";
+
+pub struct Session {
+    pub width: u32,
+    pub height: u32,
+    pub time: f64, //Ticks simulated so far.
+    pub origin: ChunkVector, //The floating origin the camera's position is relative to.
+    pub camera: Camera,
+    pub indexed: bool,
+    pub arrays: bool,
+    pub overlay: Vec<u8>, //From Overlay::to_bytes.
+    pub path: String //From CameraPath::to_text.
+}
+
+impl Session {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.put_header(MAGIC, VERSION);
+        bytes.push(GENERATOR_VERSION);
+        bytes.put_u32(self.width);
+        bytes.put_u32(self.height);
+        bytes.put_f64(self.time);
+        bytes.put_chunk(self.origin);
+        self.camera.write_state(&mut bytes);
+        bytes.push(self.indexed as u8 | (self.arrays as u8) << 1);
+        bytes.put_u32(self.overlay.len() as u32);
+        bytes.extend_from_slice(&self.overlay);
+        bytes.put_str(&self.path);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = ByteReader::new(bytes, "session");
+        let version = reader.header(MAGIC)?;
+        if version != VERSION {
+            return Err(format!("Unsupported session version {}", version));
+        }
+        check_generator(reader.u8()? as u32)?;
+
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        let time = reader.f64()?;
+        let origin = reader.chunk()?;
+        let camera = Camera::read_state(&mut reader)?;
+        let flags = reader.u8()?;
+        let overlay_len = reader.u32()? as usize;
+        let overlay = reader.take(overlay_len)?.to_vec();
+        let path = reader.string()?;
+        reader.finish()?;
+        let session = Session { width, height, time, origin, camera, indexed: flags & 1 != 0, arrays: flags & 2 != 0, overlay, path };
+        session.check()?;
+        Ok(session)
+    }
+
+    pub fn to_json(&self) -> String {
+        let cam = &self.camera;
+        let quat = cam.ori.get_quaternion();
+        let pos = self.origin.world_corner() + WorldVector::from_point(cam.pos);
+        let number = |n: f64| Json::Number(n);
+        let field = |key: &str, value: Json| (key.to_string(), value);
+
+        Json::Object(vec!(
+            field("format", Json::String(JSON_FORMAT.to_string())),
+            field("version", number(VERSION as f64)),
+            field("generator", number(GENERATOR_VERSION as f64)),
+            field("width", number(self.width as f64)),
+            field("height", number(self.height as f64)),
+            field("time", number(self.time)),
+            field("origin", Json::Array(self.origin.to_array().map(|c| number(c as f64)).to_vec())),
+            field("camera", Json::Object(vec!(
+                field("position", Json::Array(pos.to_array().map(number).to_vec())),
+                field("velocity", Json::f32s(&cam.vel.to_array())),
+                field("orientation", Json::f32s(&[quat.w, quat.x, quat.y, quat.z])),
+                field("turn_rate", Json::f32s(&cam.rpy_vel.to_array())),
+                field("target_turn_rate", Json::f32s(&cam.target_rpy_vel.to_array())),
+                field("thrust", Json::f32(cam.thrust)),
+                field("strafe", Json::f32(cam.strafe)),
+                field("lift", Json::f32(cam.lift)),
+                field("fov", Json::f32(cam.rvp.get_fov())),
+                field("max_bound", Json::f32(cam.rvp.get_maxbound())),
+                field("chunk_fov", Json::f32(cam.cvp.get_fov())),
+                field("render_distance", Json::f32(cam.cvp.get_alpha())),
+                field("gravity", Json::f32s(&cam.gravity.to_array()))
+            ))),
+            field("flight", flight_to_json(&cam.flight)),
+            field("spatial_index", Json::Bool(self.indexed)),
+            field("star_arrays", Json::Bool(self.arrays)),
+            field("overlay", Json::String(to_base64(&self.overlay))),
+            field("path", Json::String(self.path.clone()))
+        )).to_text()
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let json = Json::parse(text)?;
+        if json.get("format").and_then(Json::as_str) != Ok(JSON_FORMAT) {
+            return Err("Not a StarTest session".to_string());
+        }
+        let version = json.get("version")?.as_u32()?;
+        if version != VERSION as u32 {
+            return Err(format!("Unsupported session version {}", version));
+        }
+        check_generator(json.get("generator")?.as_u32()?)?;
+
+        let [ox, oy, oz] = json.get("origin")?.as_i32s()?;
+        let origin = ChunkVector::new(ox, oy, oz);
+        let cam = json.get("camera")?;
+        let point = |key: &str| cam.get(key)?.as_f32s().map(|[x, y, z]| PointVector::new(x, y, z));
+        let [px, py, pz] = cam.get("position")?.as_f64s()?;
+        let [w, x, y, z] = cam.get("orientation")?.as_f32s()?;
+        let camera = Camera {
+            pos: (WorldVector::new(px, py, pz) - origin.world_corner()).to_point(),
+            vel: point("velocity")?,
+            ori: Orientation::from_quaternion(Quaternion::new(w, x, y, z)),
+            rpy_vel: point("turn_rate")?,
+            target_rpy_vel: point("target_turn_rate")?,
+            rvp: Viewport::fov_maxbound(cam.get("fov")?.as_f32()?, cam.get("max_bound")?.as_f32()?),
+            cvp: Viewport::fov_alpha(cam.get("chunk_fov")?.as_f32()?, cam.get("render_distance")?.as_f32()?),
+            thrust: cam.get("thrust")?.as_f32()?,
+            strafe: cam.get("strafe")?.as_f32()?,
+            lift: cam.get("lift")?.as_f32()?,
+            thrust_scale: 1.0,
+            flight: flight_from_json(json.get("flight")?)?,
+            gravity: point("gravity")?
+        };
+
+        let session = Session {
+            width: json.get("width")?.as_u32()?,
+            height: json.get("height")?.as_u32()?,
+            time: json.get("time")?.as_f64()?,
+            origin,
+            camera,
+            indexed: json.get("spatial_index")?.as_bool()?,
+            arrays: json.get("star_arrays")?.as_bool()?,
+            overlay: from_base64(json.get("overlay")?.as_str()?).ok_or("The session's overlay is not valid base64")?,
+            path: json.get("path")?.as_str()?.to_string()
+        };
+        session.check()?;
+        Ok(session)
+    }
+
+    fn check(&self) -> Result<(), String> { //Errs on sessions that parse but can't be restored.
+        if self.width == 0 || self.height == 0 {
+            return Err("The session's screen size must be above 0".to_string());
+        }
+        if !self.time.is_finite() {
+            return Err("The session's time must be finite".to_string());
+        }
+        CameraPath::from_text(&self.path)?;
+        self.camera.check()
+    }
+}
+
+fn check_generator(version: u32) -> Result<(), String> {
+    if version != GENERATOR_VERSION as u32 {
+        return Err("Session is from a different version of the universe".to_string());
+    }
+    Ok(())
+}
+
+fn flight_to_json(flight: &FlightModel) -> Json {
+    let fields = [
+        ("mass", flight.mass),
+        ("rpy_factor", flight.rpy_factor),
+        ("resistance", flight.resistance),
+        ("base_fov", flight.base_fov),
+        ("fov_speed_scale", flight.fov_speed_scale),
+        ("fov_speed_power", flight.fov_speed_power),
+        ("max_fov", flight.max_fov),
+        ("cruise_distance", flight.cruise_distance),
+        ("gravity", flight.gravity),
+        ("avoidance", flight.avoidance),
+        ("light_speed", flight.light_speed),
+        ("assist", flight.assist)
+    ];
+    let mut json: Vec<(String, Json)> = fields.into_iter().map(|(key, value)| (key.to_string(), Json::f32(value))).collect();
+    json.insert(3, ("rotational_inertia".to_string(), Json::Bool(flight.rotational_inertia)));
+    Json::Object(json)
+}
+
+fn flight_from_json(json: &Json) -> Result<FlightModel, String> {
+    let get = |key: &str| json.get(key)?.as_f32();
+    Ok(FlightModel {
+        mass: get("mass")?,
+        rpy_factor: get("rpy_factor")?,
+        resistance: get("resistance")?,
+        rotational_inertia: json.get("rotational_inertia")?.as_bool()?,
+        base_fov: get("base_fov")?,
+        fov_speed_scale: get("fov_speed_scale")?,
+        fov_speed_power: get("fov_speed_power")?,
+        max_fov: get("max_fov")?,
+        cruise_distance: get("cruise_distance")?,
+        gravity: get("gravity")?,
+        avoidance: get("avoidance")?,
+        light_speed: get("light_speed")?,
+        assist: get("assist")?
+    })
+}
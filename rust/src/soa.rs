@@ -0,0 +1,108 @@
+use std::{collections::HashMap, mem::size_of};
+
+use crate::{chunk::Chunk, vector3::{ChunkVector, PointVector}};
+
+//Every loaded star in one set of flat arrays, so projection is a simple loop the compiler can vectorise. Unloaded chunks
+//leave a gap that is filled with NaN (which never passes the on screen checks) until there are enough gaps to be worth
+//compacting.
+#[derive(Default)]
+pub struct StarArrays {
+    pub xs: Vec<f32>,
+    pub ys: Vec<f32>,
+    pub zs: Vec<f32>,
+    pub ids: Vec<u32>,
+    ranges: HashMap<ChunkVector, (usize, usize)>, //Start and length of each chunk's stars.
+    dead: usize //Number of slots belonging to unloaded chunks.
+}
+
+impl StarArrays {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, chunk: &Chunk) { //The chunk must not already be stored.
+        if chunk.stars.is_empty() {
+            return;
+        }
+
+        self.ranges.insert(chunk.pos, (self.xs.len(), chunk.stars.len()));
+        for star in &chunk.stars {
+            self.xs.push(star.x);
+            self.ys.push(star.y);
+            self.zs.push(star.z);
+        }
+        self.ids.extend_from_slice(&chunk.ids);
+    }
+
+    pub fn remove(&mut self, pos: ChunkVector) {
+        if let Some((start, len)) = self.ranges.remove(&pos) {
+            for xs in [&mut self.xs, &mut self.ys, &mut self.zs] {
+                xs[start..start+len].fill(f32::NAN);
+            }
+            self.dead += len;
+
+            if self.dead > self.xs.len()/2 {
+                self.compact();
+            }
+        }
+    }
+
+    pub fn rebase(&mut self, shift: PointVector) {
+        for (xs, s) in [(&mut self.xs, shift.x), (&mut self.ys, shift.y), (&mut self.zs, shift.z)] {
+            xs.iter_mut().for_each(|x| *x -= s);
+        }
+    }
+
+    fn compact(&mut self) { //Moves the live ranges down over the gaps, keeping their order.
+        let mut ranges: Vec<(ChunkVector, (usize, usize))> = self.ranges.drain().collect();
+        ranges.sort_by_key(|(_, (start, _))| *start);
+
+        let mut end = 0;
+        for (pos, (start, len)) in ranges {
+            for xs in [&mut self.xs, &mut self.ys, &mut self.zs] {
+                xs.copy_within(start..start+len, end);
+            }
+            self.ids.copy_within(start..start+len, end);
+            self.ranges.insert(pos, (end, len));
+            end += len;
+        }
+
+        self.xs.truncate(end);
+        self.ys.truncate(end);
+        self.zs.truncate(end);
+        self.ids.truncate(end);
+        self.dead = 0;
+    }
+
+    pub fn memory_usage(&self) -> usize { //Approximate, in bytes.
+        (self.xs.capacity() + self.ys.capacity() + self.zs.capacity()) * size_of::<f32>() +
+            self.ids.capacity() * size_of::<u32>() +
+            self.ranges.capacity() * size_of::<(ChunkVector, (usize, usize))>()
+    }
+
+    //Transforms every star into camera space. Fills scales with alpha/depth and xs and ys with screen coordinates,
+    //matching what Projector::project_point returns.
+    #[allow(clippy::needless_range_loop)] //Indexing equal length slices lets the bounds checks be dropped.
+    pub fn project(&self, cam_pos: PointVector, dirs: &[PointVector; 3], alpha: f32, scales: &mut Vec<f32>, xs: &mut Vec<f32>, ys: &mut Vec<f32>) {
+        let n = self.xs.len();
+        scales.resize(n, 0.0);
+        xs.resize(n, 0.0);
+        ys.resize(n, 0.0);
+
+        let (px, py, pz) = (&self.xs[..n], &self.ys[..n], &self.zs[..n]);
+        let (scales, xs, ys) = (&mut scales[..n], &mut xs[..n], &mut ys[..n]);
+        let [dx, dy, dz] = dirs;
+        for i in 0..n {
+            let rx = px[i] - cam_pos.x;
+            let ry = py[i] - cam_pos.y;
+            let rz = pz[i] - cam_pos.z;
+            let lx = rx*dx.x + ry*dx.y + rz*dx.z;
+            let ly = rx*dy.x + ry*dy.y + rz*dy.z;
+            let lz = rx*dz.x + ry*dz.y + rz*dz.z;
+            let scale = alpha/lz;
+            scales[i] = scale;
+            xs[i] = lx*scale;
+            ys[i] = -ly*scale;
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//Sorting projected stars by depth, so nearer stars can be drawn over further ones. A radix sort on the bits of each
+//star's scale keeps this linear in the number of stars drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DepthOrder {
+    None, //Whatever order the chunks come in.
+    BackToFront,
+    FrontToBack
+}
+
+impl DepthOrder {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "none" => Ok(DepthOrder::None),
+            "back_to_front" => Ok(DepthOrder::BackToFront),
+            "front_to_back" => Ok(DepthOrder::FrontToBack),
+            _ => Err(format!("Unknown depth order \"{}\"", name))
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DepthOrder::None => "none",
+            DepthOrder::BackToFront => "back_to_front",
+            DepthOrder::FrontToBack => "front_to_back"
+        }
+    }
+}
+
+//Reorders the records in out, each stride numbers long, by their scales. Scales are alpha over depth, so bigger is
+//nearer. They must all be positive, which makes their bits sort the same way they do.
+pub fn sort_by_depth(out: &mut [f32], stride: usize, scales: &[f32], order: DepthOrder) {
+    if order == DepthOrder::None || scales.len() < 2 {
+        return;
+    }
+    let keys = scales.iter().map(|s| if order == DepthOrder::BackToFront {s.to_bits()} else {!s.to_bits()});
+    let sorted = radix_sort(keys.collect());
+
+    let mut sorted_out = Vec::with_capacity(scales.len()*stride);
+    for (_, i) in sorted {
+        let i = i as usize*stride;
+        sorted_out.extend_from_slice(&out[i..i + stride]);
+    }
+    out[..sorted_out.len()].copy_from_slice(&sorted_out);
+}
+
+//Sorts the keys least significant byte first, returning each with the index it started at. Stable, and skips bytes
+//that are the same for every key, which the top bytes of nearby floats usually are.
+fn radix_sort(keys: Vec<u32>) -> Vec<(u32, u32)> {
+    let mut items: Vec<(u32, u32)> = keys.into_iter().zip(0..).collect();
+    let mut spare = vec!((0, 0); items.len());
+    for shift in [0, 8, 16, 24] {
+        let mut counts = [0usize; 256];
+        for (key, _) in &items {
+            counts[(key >> shift & 0xFF) as usize] += 1;
+        }
+        if counts.contains(&items.len()) {
+            continue;
+        }
+        let mut start = 0;
+        for count in counts.iter_mut() {
+            (*count, start) = (start, start + *count);
+        }
+        for item in &items {
+            let bucket = &mut counts[(item.0 >> shift & 0xFF) as usize];
+            spare[*bucket] = *item;
+            *bucket += 1;
+        }
+        std::mem::swap(&mut items, &mut spare);
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, prelude::StdRng, Rng};
+
+    use super::*;
+
+    #[test]
+    fn sorts_records() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let scales: Vec<f32> = (0..5000).map(|_| rng.gen_range(0.001..100.0)).collect();
+        let mut out: Vec<f32> = scales.iter().flat_map(|s| [*s, -s]).collect();
+
+        sort_by_depth(&mut out, 2, &scales, DepthOrder::BackToFront);
+        assert!(out.chunks(2).all(|r| r[1] == -r[0]));
+        assert!(out.chunks(2).zip(out.chunks(2).skip(1)).all(|(a, b)| a[0] <= b[0]));
+
+        let scales: Vec<f32> = out.iter().step_by(2).copied().collect();
+        sort_by_depth(&mut out, 2, &scales, DepthOrder::FrontToBack);
+        assert!(out.chunks(2).zip(out.chunks(2).skip(1)).all(|(a, b)| a[0] >= b[0]));
+    }
+}
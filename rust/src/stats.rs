@@ -0,0 +1,58 @@
+use wasm_bindgen::prelude::wasm_bindgen;
+
+pub const HISTOGRAM_BUCKETS: usize = 8;
+
+//Counts of how long chunk updates that generated chunks took. Bucket 0 is under 1ms, bucket n covers 2^(n-1) to 2^n ms and the last bucket
+//holds everything slower.
+#[derive(Clone, Default)]
+pub struct GenerationHistogram {
+    buckets: [u32; HISTOGRAM_BUCKETS]
+}
+
+impl GenerationHistogram {
+    pub fn record(&mut self, ms: f64) {
+        let mut bucket = 0;
+        let mut limit = 1.0;
+        while ms >= limit && bucket < HISTOGRAM_BUCKETS - 1 {
+            bucket += 1;
+            limit *= 2.0;
+        }
+        self.buckets[bucket] += 1;
+    }
+
+    pub fn buckets(&self) -> &[u32] {
+        &self.buckets
+    }
+}
+
+#[wasm_bindgen]
+pub struct UniverseStats {
+    pub loaded_chunks: usize,
+    pub bounds_volume: f64, //Volume of the loaded region in cubic units.
+    pub total_stars: usize,
+    pub memory_bytes: usize, //Approximate heap and struct memory used by loaded chunks, the spatial index and star arrays.
+    pub chunks_generated: usize, //During the last update.
+    pub chunks_reused: usize,
+    histogram: GenerationHistogram
+}
+
+#[wasm_bindgen]
+impl UniverseStats {
+    pub fn generation_histogram(&self) -> Vec<u32> { //See GenerationHistogram for the bucket ranges.
+        self.histogram.buckets().to_vec()
+    }
+}
+
+impl UniverseStats {
+    pub fn new(
+        loaded_chunks: usize,
+        bounds_volume: f64,
+        total_stars: usize,
+        memory_bytes: usize,
+        chunks_generated: usize,
+        chunks_reused: usize,
+        histogram: GenerationHistogram
+    ) -> Self {
+        UniverseStats { loaded_chunks, bounds_volume, total_stars, memory_bytes, chunks_generated, chunks_reused, histogram }
+    }
+}
@@ -1,16 +1,36 @@
 use core::panic;
-use std::cmp::max;
+use std::{cell::RefCell, cmp::max};
 
 use wasm_bindgen::prelude::wasm_bindgen;
 
-use crate::{camera::{Camera, Projector}, chunkstore::ChunkStore, viewport::Viewport};
+use crate::{autopilot::Autopilot, orbit::Orbit, camera_path::{CameraPath, Keyframe}, orientation::Orientation, camera::{Camera, Projector, Frustum}, chunkstore::ChunkStore, viewport::Viewport, vector3::{PointVector, ChunkVector, WorldVector}, overlay::{Overlay, StarId}, stats::UniverseStats, recording::{Input, InputLog, StartState, View}, bytes::ByteReader, flight::FlightModel, collision::{Contacts, StarContact, MAX_STAR_RADIUS, entry, star_radius}, chunk::Chunk, matrix::Matrix3, jump::{Jump, JumpStep, WARP_FOV, PREFETCH_CHUNKS}, location::Location, session::Session, projection::{Projection, ProjectionMode, Rectilinear, Fisheye, Equirectangular, Stereographic}, layout::{Field, Layout, Projected}, sort::{DepthOrder, sort_by_depth}};
 
 // const RENDER_DISTANCE:f32 = 1536.0;
 const FOV: f32 = 75.0f32;
+const REBASE_CHUNKS: i32 = 8; //How far the camera can get from the floating origin before it is moved.
+const ZOOM_FACTOR: f32 = 0.002; //Orbit zoom rate per unit of thrust.
+const CRUISE_RANGE: f32 = 4.0; //How many cruise distances to look for the nearest star within. Sets the top cruise speed.
+const MIN_THRUST_SCALE: f32 = 0.01;
+const GRAVITY_RANGE: f32 = 256.0; //Stars further away than this don't pull on the camera.
 #[wasm_bindgen]
 pub struct Universe {
     camera: Camera,
     chunk_store: ChunkStore,
+    autopilot: Option<Autopilot>,
+    orbit: Option<Orbit>,
+    path: CameraPath,
+    path_time: Option<f32>, //Set while the path is playing.
+    jump: Option<Jump>,
+    recording: Option<InputLog>,
+    contacts: Contacts,
+    projection: ProjectionMode,
+    stereo: f32, //The distance between the eyes, or 0 for one view.
+    layout: Layout, //For project_stars_layout.
+    depth_order: DepthOrder,
+    previous: Camera, //The camera before the last tick, and the floating origin it was relative to.
+    previous_origin: ChunkVector,
+    projected: RefCell<[Vec<f32>; 3]>, //Scales, xs and ys from the star arrays, kept so projecting doesn't allocate each frame.
+    time: f64, //Ticks simulated so far.
     width: u32,
     height: u32
 }
@@ -20,10 +40,25 @@ impl Universe {
     //The default render distance is 1536.
     pub fn new(width: u32, height: u32, render_distance: f32) -> Self{
         let camera = Camera::new(max(width, height) as f32, FOV.to_radians(), render_distance);
-        let chunk_store = ChunkStore::start(&camera);
+        let chunk_store = ChunkStore::start(&camera, ChunkVector::new(0, 0, 0), Overlay::new());
         Universe{
+            previous: camera.clone(),
+            previous_origin: ChunkVector::new(0, 0, 0),
             camera,
             chunk_store,
+            autopilot: None,
+            orbit: None,
+            path: CameraPath::new(),
+            path_time: None,
+            jump: None,
+            recording: None,
+            contacts: Contacts::default(),
+            projection: ProjectionMode::Rectilinear,
+            stereo: 0.0,
+            layout: Layout::new(vec!(Field::Size, Field::X, Field::Y)),
+            depth_order: DepthOrder::None,
+            projected: RefCell::default(),
+            time: 0.0,
             width,
             height
         }
@@ -34,48 +69,98 @@ impl Universe {
     }
 
     pub fn project_stars(&self, arr: &mut [f32]) -> u32{ //Returns the number of stars that are in front of the camera.
-        let mut index = 0usize;
-        let star_iter = self.chunk_store.iter();
-        let projector = Projector::new(&self.camera, &self.camera.rvp);
-        let point_iterator = star_iter.map(|star| {
-            projector.project_point(star)
-        });
+        self.project_into(arr, &Layout::new(vec!(Field::Size, Field::X, Field::Y)))
+    }
 
-        let mut render_star_count = 0;
-        let max_dist = self.camera.cvp.get_alpha() / self.camera.rvp.get_alpha();
-        for point in point_iterator { //crash here caused by out of bounds
-            if let Some(p) = point {
-                
-                let ax = p.y + (self.width/2) as f32;
-                let ay = p.z + (self.height/2) as f32;
-                if
-                    p.x > 0.0 &&
-                    (1.0/p.x) < max_dist &&
-                    ax >= 0.0 && ax < self.width as f32 &&
-                    ay >= 0.0 && ay < self.height as f32
-                {
-                    let scale = p.x/50.0; //Moved scale here to prevent a /0 crash.
-                    let offset = scale/2.0;
-                    arr[index] = scale;
-                    arr[index+1] = ax-offset;
-                    arr[index+2] = ay-offset;
-                    render_star_count += 1;
-                    index+=3;
-                }
-            }
-        }
+    //Like project_stars, but with five numbers per star: scale, x, y, then the Doppler shift as a factor on the star's
+    //colour temperature and a factor on its brightness. The shifts are 1 unless the flight model sets a speed of light.
+    pub fn project_stars_shifted(&self, arr: &mut [f32]) -> u32 {
+        self.project_into(arr, &Layout::new(vec!(Field::Size, Field::X, Field::Y, Field::Shift, Field::Beaming)))
+    }
 
-        render_star_count
+    //Like project_stars, but writes the fields chosen with set_output_layout.
+    pub fn project_stars_layout(&self, arr: &mut [f32]) -> u32 {
+        self.project_into(arr, &self.layout)
+    }
+
+    //Field names separated by commas, out of id, depth, x, y, size, r, g, b, brightness, shift, beaming, prev_x and
+    //prev_y. Asking for id, colour or brightness skips the spatial index and star arrays, so it's slower. prev_x and
+    //prev_y are where the star was on screen before the last tick, for drawing it as a streak.
+    pub fn set_output_layout(&mut self, fields: &str) -> Result<(), String> {
+        self.layout = Layout::parse(fields)?;
+        self.record(Input::Layout(self.layout.clone()));
+        Ok(())
+    }
+
+    //Describes the output layout as JSON, with a version, the numbers per star and each field's name, offset and count.
+    pub fn get_output_layout(&self) -> String {
+        self.layout.describe()
+    }
+
+    //"rectilinear", "fisheye", "equirectangular" or "stereographic". fov is the angle in radians across the wider side of
+    //the screen for fisheye and stereographic, up to 360° for fisheye, and ignored by the others. The wide projections
+    //load every chunk within the render distance, which takes more memory.
+    pub fn set_projection(&mut self, name: &str, fov: f32) -> Result<(), String> {
+        self.projection = ProjectionMode::from_name(name, fov)?;
+        self.record(Input::Projection(self.projection));
+        self.chunk_store.set_all_round(self.projection != ProjectionMode::Rectilinear);
+        Ok(())
+    }
+
+    pub fn get_projection(&self) -> String {
+        self.projection.name().to_string()
+    }
+
+    //Draws the view twice side by side, from eyes this far apart along the camera's x axis, for viewers that hold a phone
+    //up to the eyes. Each eye gets half the screen and the arrays passed to project_stars need room for twice as many
+    //stars. 0 turns it off.
+    pub fn set_stereo(&mut self, eye_distance: f32) {
+        self.stereo = eye_distance.max(0.0);
+        self.record(Input::Stereo(self.stereo));
+    }
+
+    //"none", "back_to_front" or "front_to_back". Sorts the stars each project_stars call writes by their depth, so
+    //nearer stars can be drawn over further ones. In stereo each eye's stars are sorted separately.
+    pub fn set_depth_order(&mut self, name: &str) -> Result<(), String> {
+        self.depth_order = DepthOrder::from_name(name)?;
+        self.record(Input::DepthOrder(self.depth_order));
+        Ok(())
     }
 
     pub fn set_size(&mut self, width: u32, height: u32) {
+        self.record(Input::Resize(width, height));
         self.camera.rvp = Viewport::fov_maxbound(FOV.to_radians(), max(width, height) as f32);
         self.width = width;
         self.height = height;
     }
 
     pub fn tick(&mut self, delta: f32) {
-        self.camera.tick(delta);
+        self.record(Input::Ticks(1, delta));
+        self.keep_previous();
+        self.time += delta as f64;
+        let mut start = self.camera.pos;
+        self.camera.thrust_scale = self.thrust_scale();
+        if let Some(autopilot) = &self.autopilot {
+            let target = self.chunk_store.to_relative(autopilot.target);
+            if !autopilot.steer(&mut self.camera, target) {
+                self.autopilot = None;
+            }
+        }
+        if self.jump.is_some() {
+            self.play_jump(delta);
+            start = self.camera.pos; //Don't count the stars between here and the target as touched.
+        } else if self.path_time.is_some() {
+            self.play_path(delta);
+        } else if let Some(orbit) = &mut self.orbit {
+            let center = self.chunk_store.to_relative(orbit.center);
+            let start_pos = self.chunk_store.to_relative(orbit.start_pos());
+            orbit.tick(&mut self.camera, center, start_pos, delta);
+        } else {
+            let stars = self.pulling_stars(delta);
+            self.camera.tick(delta, &stars);
+        }
+        self.check_contacts(start);
+        self.rebase_origin();
         // let now_a = Date::now();
         self.chunk_store.update(&self.camera);
         // let time = Date::now() - now_a;
@@ -84,20 +169,316 @@ impl Universe {
         // }
     }
 
+    //While orbiting, yaw and pitch move around the point and thrust zooms in and out. Roll does nothing.
     pub fn set_camera_roll_vel(&mut self, roll: f32){
+        self.record(Input::Roll(roll));
         self.camera.target_rpy_vel.x = roll;
     }
 
     pub fn set_camera_pitch_vel(&mut self, pitch: f32){
+        self.record(Input::Pitch(pitch));
         self.camera.target_rpy_vel.y = pitch;
+        if let Some(orbit) = &mut self.orbit {
+            orbit.target_rates.y = pitch;
+        }
     }
     
     pub fn set_camera_yaw_vel(&mut self, yaw: f32){
+        self.record(Input::Yaw(yaw));
         self.camera.target_rpy_vel.z = yaw;
+        if let Some(orbit) = &mut self.orbit {
+            orbit.target_rates.x = yaw;
+        }
     }
 
     pub fn set_thrust(&mut self, speed: f32) {
+        self.record(Input::Thrust(speed));
         self.camera.thrust = speed;
+        if let Some(orbit) = &mut self.orbit {
+            orbit.target_rates.z = speed*ZOOM_FACTOR;
+        }
+    }
+
+    pub fn set_strafe(&mut self, speed: f32) { //Thrust to the right, or left if negative. Only used in free flight.
+        self.record(Input::Strafe(speed));
+        self.camera.strafe = speed;
+    }
+
+    pub fn set_lift(&mut self, speed: f32) { //Thrust upwards, or down if negative.
+        self.record(Input::Lift(speed));
+        self.camera.lift = speed;
+    }
+
+    pub fn set_flight_model(&mut self, model: &FlightModel) -> Result<(), String> { //Only affects free flight. See FlightModel::check.
+        model.check()?;
+        self.record(Input::Flight(*model));
+        self.camera.flight = *model;
+        Ok(())
+    }
+
+    pub fn set_flight_preset(&mut self, name: &str) -> Result<(), String> { //"arcade", "newtonian", "assisted" or "cruise".
+        self.set_flight_model(&FlightModel::preset(name)?)
+    }
+
+    pub fn get_flight_model(&self) -> FlightModel {
+        self.camera.flight
+    }
+
+    //Returns the next time the camera entered or left a star, oldest first, or nothing once they've all been read.
+    //Checked every tick whatever is moving the camera.
+    pub fn next_star_contact(&mut self) -> Option<StarContact> {
+        self.contacts.next()
+    }
+
+    //Flies to a point and stops standoff units away from it. Overrides the camera controls until it arrives or is cancelled.
+    pub fn autopilot_to(&mut self, x: f64, y: f64, z: f64, standoff: f32) {
+        self.stop_orbit();
+        self.autopilot = Some(Autopilot::new(WorldVector::new(x, y, z), standoff));
+    }
+
+    pub fn autopilot_to_star(&mut self, id: &StarId, standoff: f32) -> bool { //Returns false if the star doesn't exist.
+        match self.chunk_store.star_position(id) {
+            Some(star) => {
+                let star = self.chunk_store.to_world(star);
+                self.autopilot_to(star.x, star.y, star.z, standoff);
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn cancel_autopilot(&mut self) {
+        if self.autopilot.take().is_some() {
+            self.camera.target_rpy_vel = PointVector::zeros();
+            self.camera.cut_thrust();
+        }
+    }
+
+    pub fn is_autopilot_active(&self) -> bool {
+        self.autopilot.is_some()
+    }
+
+    pub fn orbit_point(&mut self, x: f64, y: f64, z: f64, radius: f32) { //Swings the camera round to circle a point.
+        self.cancel_autopilot();
+        let world_center = WorldVector::new(x, y, z);
+        let center = self.chunk_store.to_relative(world_center);
+        let start_pos = self.chunk_store.to_world(self.camera.pos);
+        self.orbit = Some(Orbit::new(&self.camera, center, world_center, start_pos, radius));
+    }
+
+    pub fn orbit_star(&mut self, id: &StarId, radius: f32) -> bool { //Returns false if the star doesn't exist.
+        match self.chunk_store.star_position(id) {
+            Some(star) => {
+                let star = self.chunk_store.to_world(star);
+                self.orbit_point(star.x, star.y, star.z, radius);
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn stop_orbit(&mut self) { //Back to free flight, drifting with the orbit's last motion.
+        if self.orbit.take().is_some() {
+            self.camera.target_rpy_vel = PointVector::zeros();
+            self.camera.cut_thrust();
+        }
+    }
+
+    pub fn is_orbiting(&self) -> bool {
+        self.orbit.is_some()
+    }
+
+    //Warps to a point too far away to fly to. The chunks there are generated over the spool up, so arriving doesn't
+    //stall. Takes over from the other camera modes until it's done.
+    pub fn jump_to(&mut self, x: f64, y: f64, z: f64) {
+        self.cancel_autopilot();
+        self.stop_orbit();
+        self.stop_camera_path();
+        self.camera.vel = PointVector::zeros();
+        self.camera.rpy_vel = PointVector::zeros();
+        self.camera.target_rpy_vel = PointVector::zeros();
+        self.camera.cut_thrust();
+        self.jump = Some(Jump::new(WorldVector::new(x, y, z), self.camera.rvp.get_fov()));
+    }
+
+    pub fn is_jumping(&self) -> bool {
+        self.jump.is_some()
+    }
+
+    pub fn add_keyframe(&mut self, time: f32) { //Records the camera's current pose. Replaces any keyframe at the same time.
+        self.path.add(Keyframe {
+            time,
+            pos: self.chunk_store.to_world(self.camera.pos),
+            rot: self.camera.ori.get_quaternion(),
+            fov: self.camera.rvp.get_fov()
+        });
+    }
+
+    pub fn clear_keyframes(&mut self) {
+        self.path = CameraPath::new();
+        self.path_time = None;
+    }
+
+    pub fn keyframe_count(&self) -> usize {
+        self.path.len()
+    }
+
+    pub fn play_camera_path(&mut self) -> bool { //Plays from the first keyframe, taking over from the flight controls. Returns false if there are no keyframes.
+        if self.path.is_empty() {
+            return false;
+        }
+        self.cancel_autopilot();
+        self.stop_orbit();
+        self.jump = None;
+        self.chunk_store.clear_prefetch();
+        self.path_time = Some(self.path.start_time());
+        self.play_path(0.0);
+        true
+    }
+
+    pub fn stop_camera_path(&mut self) {
+        self.path_time = None;
+    }
+
+    pub fn is_playing_camera_path(&self) -> bool {
+        self.path_time.is_some()
+    }
+
+    pub fn camera_path_to_text(&self) -> String {
+        self.path.to_text()
+    }
+
+    pub fn load_camera_path(&mut self, text: &str) -> Result<(), String> { //Replaces the keyframes. Leaves them alone if the text is bad.
+        self.path = CameraPath::from_text(text)?;
+        self.path_time = None;
+        Ok(())
+    }
+
+    //Records every tick, resize, flight control, pose call and change to how the stars are drawn from now on so
+    //replay_inputs can reproduce the flight and what project_stars gives exactly. The loaded chunks are regenerated so
+    //the replay starts from the same state. Autopilot, orbits, camera paths, jumps and star edits aren't recorded, so a
+    //replay of a recording that used them will go its own way.
+    pub fn start_recording(&mut self) {
+        self.chunk_store.restart(&self.camera);
+        let mut camera = vec!();
+        self.camera.write_state(&mut camera);
+        self.recording = Some(InputLog::new(StartState {
+            width: self.width,
+            height: self.height,
+            origin: self.chunk_store.origin(),
+            camera,
+            indexed: self.chunk_store.index().is_some(),
+            arrays: self.chunk_store.arrays().is_some(),
+            overlay: self.chunk_store.overlay().to_bytes(),
+            view: View {
+                projection: self.projection,
+                stereo: self.stereo,
+                depth_order: self.depth_order,
+                layout: self.layout.clone()
+            }
+        }));
+    }
+
+    pub fn stop_recording(&mut self) -> Vec<u8> { //Returns the input log, or nothing if there wasn't a recording.
+        self.recording.take().map_or(vec!(), |log| log.to_bytes())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn get_time(&self) -> f64 { //Total of the deltas passed to tick.
+        self.time
+    }
+
+    //Saves the camera, flight model, viewport size, clock, star edits and keyframes so load_session can pick up from
+    //here after a reload.
+    pub fn save_session(&self) -> Vec<u8> {
+        self.session().to_bytes()
+    }
+
+    pub fn save_session_json(&self) -> String { //The same as save_session, but readable.
+        self.session().to_json()
+    }
+
+    //Replaces the whole state with a saved session and generates the chunks around the camera again. Stops any
+    //autopilot, orbit, camera path, jump or recording. Leaves everything alone if the session is bad.
+    pub fn load_session(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.restore(Session::from_bytes(bytes)?)
+    }
+
+    pub fn load_session_json(&mut self, text: &str) -> Result<(), String> {
+        self.restore(Session::from_json(text)?)
+    }
+
+    pub fn get_position(&self) -> Vec<f64> { //[x, y, z] in world coordinates.
+        self.chunk_store.to_world(self.camera.pos).to_array().to_vec()
+    }
+
+    pub fn set_position(&mut self, x: f64, y: f64, z: f64) { //Chunks around the new position are loaded on the next tick.
+        let pos = WorldVector::new(x, y, z);
+        self.record(Input::Position(pos));
+        self.camera.pos = self.chunk_store.to_relative(pos);
+        self.rebase_origin();
+        self.keep_previous();
+    }
+
+    pub fn get_velocity(&self) -> Vec<f32> { //[x, y, z] in units per tick.
+        self.camera.vel.to_array().to_vec()
+    }
+
+    pub fn set_velocity(&mut self, x: f32, y: f32, z: f32) {
+        let vel = PointVector::new(x, y, z);
+        self.record(Input::Velocity(vel));
+        self.camera.vel = vel;
+    }
+
+    pub fn get_orientation_euler(&self) -> Vec<f32> { //[roll, pitch, yaw] in radians. See set_orientation_euler.
+        self.camera.ori.to_euler().to_array().to_vec()
+    }
+
+    //Yaws about the world's Y axis, then pitches about the camera's X axis, then rolls about its Z axis, starting from
+    //looking along +Z. Positive pitch tips the view down.
+    pub fn set_orientation_euler(&mut self, roll: f32, pitch: f32, yaw: f32) {
+        let rpy = PointVector::new(roll, pitch, yaw);
+        self.record(Input::Euler(rpy));
+        self.camera.ori = Orientation::new(rpy);
+        self.keep_previous();
+    }
+
+    //Turns the camera to face a point in world coordinates, keeping its top as close to up as it can. Returns false
+    //and leaves the camera alone if it's already at the point.
+    pub fn look_at(&mut self, x: f64, y: f64, z: f64, up_x: f32, up_y: f32, up_z: f32) -> bool {
+        let point = WorldVector::new(x, y, z);
+        let up = PointVector::new(up_x, up_y, up_z);
+        self.record(Input::LookAt(point, up));
+        let forward = self.chunk_store.to_relative(point) - self.camera.pos;
+        if forward.magnitude() == 0.0 {
+            return false;
+        }
+        self.camera.ori = Orientation::look_at(forward, up);
+        self.keep_previous();
+        true
+    }
+
+    //A short code for the camera's position and direction, safe to put in a URL. See load_location_code.
+    pub fn location_code(&self) -> String {
+        Location { pos: self.chunk_store.to_world(self.camera.pos), rpy: self.camera.ori.to_euler() }.to_code()
+    }
+
+    //Moves the camera to where a location code was made and stops it there, taking over from any autopilot, orbit,
+    //camera path or jump. Leaves the camera alone if the code is bad or from a different version of the universe.
+    pub fn load_location_code(&mut self, code: &str) -> Result<(), String> {
+        let location = Location::from_code(code)?;
+        self.cancel_autopilot();
+        self.stop_orbit();
+        self.stop_camera_path();
+        self.jump = None;
+        self.chunk_store.clear_prefetch();
+        self.set_position(location.pos.x, location.pos.y, location.pos.z);
+        self.set_velocity(0.0, 0.0, 0.0);
+        self.set_orientation_euler(location.rpy.x, location.rpy.y, location.rpy.z);
+        Ok(())
     }
 
     pub fn get_camera_vecs(&self, vecs: &mut [f32]) {
@@ -111,7 +492,701 @@ impl Universe {
         }
     }
 
+    pub fn set_spatial_index(&mut self, enabled: bool) { //Speeds up spatial queries and culling at the cost of some memory.
+        self.chunk_store.set_indexed(enabled);
+    }
+
+    //Positions passed to and from JS are absolute world coordinates as f64s so they stay precise far from home.
+    pub fn set_star_arrays(&mut self, enabled: bool) { //Keeps a flat copy of the stars that projects faster, at the cost of some memory.
+        self.chunk_store.set_arrays(enabled);
+    }
+
+    pub fn stars_in_sphere(&self, x: f64, y: f64, z: f64, radius: f32) -> Vec<f64> { //Returns star positions as [x, y, z, ...].
+        let mut found = vec!();
+        let center = self.chunk_store.to_relative(WorldVector::new(x, y, z));
+        self.chunk_store.for_each_in_sphere(center, radius, |star| {
+            found.extend_from_slice(&self.chunk_store.to_world(*star).to_array());
+        });
+        found
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn ray_cast(&self, ox: f64, oy: f64, oz: f64, dx: f32, dy: f32, dz: f32, max_dist: f32, hit_radius: f32) -> Vec<f64> { //Returns [x, y, z, distance] of the nearest star hit, or nothing.
+        let dir = PointVector::new(dx, dy, dz);
+        let length = dir.magnitude();
+        if length == 0.0 {
+            return vec!();
+        }
+
+        let origin = self.chunk_store.to_relative(WorldVector::new(ox, oy, oz));
+        match self.chunk_store.ray_cast(origin, dir/length, max_dist, hit_radius) {
+            Some((star, dist)) => {
+                let star = self.chunk_store.to_world(star);
+                vec!(star.x, star.y, star.z, dist as f64)
+            },
+            None => vec!()
+        }
+    }
+
+    pub fn stats(&self) -> UniverseStats {
+        self.chunk_store.stats()
+    }
+
+    pub fn find_star(&self, x: f64, y: f64, z: f64, radius: f32) -> Option<StarId> { //Finds the closest loaded star to a point.
+        let point = self.chunk_store.to_relative(WorldVector::new(x, y, z));
+        self.chunk_store.find_star(point, radius).map(|(id, _)| id)
+    }
+
+    pub fn add_star(&mut self, x: f64, y: f64, z: f64, metadata: String) -> StarId {
+        let id = self.chunk_store.overlay_mut().add_star(WorldVector::new(x, y, z), metadata);
+        self.chunk_store.refresh_chunk(id.chunk());
+        id
+    }
+
+    pub fn remove_star(&mut self, id: &StarId) -> bool {
+        let removed = self.chunk_store.overlay_mut().remove_star(id);
+        self.chunk_store.refresh_chunk(id.chunk());
+        removed
+    }
+
+    pub fn move_star(&mut self, id: &StarId, x: f64, y: f64, z: f64) -> Option<StarId> { //The star's id changes if it moves to another chunk.
+        let moved = self.chunk_store.overlay_mut().move_star(id, WorldVector::new(x, y, z));
+        self.chunk_store.refresh_chunk(id.chunk());
+        if let Some(new_id) = moved {
+            self.chunk_store.refresh_chunk(new_id.chunk());
+        }
+        moved
+    }
+
+    pub fn set_star_metadata(&mut self, id: &StarId, metadata: String) -> bool {
+        self.chunk_store.overlay_mut().set_metadata(id, metadata)
+    }
+
+    pub fn get_star_metadata(&self, id: &StarId) -> Option<String> {
+        self.chunk_store.overlay().get_metadata(id).map(str::to_string)
+    }
+
+    pub fn save_overlay(&self) -> Vec<u8> {
+        self.chunk_store.overlay().to_bytes()
+    }
+
+    pub fn load_overlay(&mut self, bytes: &[u8]) -> Result<(), String> { //Replaces all edits. Leaves the current ones in place if the data is bad.
+        let overlay = Overlay::from_bytes(bytes)?;
+        self.chunk_store.set_overlay(overlay);
+        Ok(())
+    }
+
+    pub fn clear_overlay(&mut self) {
+        self.chunk_store.set_overlay(Overlay::new());
+    }
+
     pub fn get_render_dist_ratio(&self) -> f32{
         self.camera.rvp.get_maxbound()/self.camera.cvp.get_maxbound()
     }
-}
\ No newline at end of file
+}
+
+impl Universe {
+    pub fn replay(log: &[u8]) -> Result<Self, String> { //Builds the universe a recording started from and plays its inputs.
+        let log = InputLog::from_bytes(log)?;
+        let start = &log.start;
+        let mut reader = ByteReader::new(&start.camera, "camera state");
+        let camera = Camera::read_state(&mut reader)?;
+        reader.finish()?;
+        camera.check()?;
+
+        let mut chunk_store = ChunkStore::start(&camera, start.origin, Overlay::from_bytes(&start.overlay)?);
+        chunk_store.set_indexed(start.indexed);
+        chunk_store.set_arrays(start.arrays);
+        chunk_store.set_all_round(start.view.projection != ProjectionMode::Rectilinear);
+        let mut universe = Universe {
+            previous: camera.clone(),
+            previous_origin: start.origin,
+            camera,
+            chunk_store,
+            autopilot: None,
+            orbit: None,
+            path: CameraPath::new(),
+            path_time: None,
+            jump: None,
+            recording: None,
+            contacts: Contacts::default(),
+            projection: start.view.projection,
+            stereo: start.view.stereo,
+            layout: start.view.layout.clone(),
+            depth_order: start.view.depth_order,
+            projected: RefCell::default(),
+            time: 0.0,
+            width: start.width,
+            height: start.height
+        };
+
+        for input in &log.inputs {
+            match *input {
+                Input::Projection(mode) => {
+                    universe.projection = mode;
+                    universe.chunk_store.set_all_round(mode != ProjectionMode::Rectilinear);
+                },
+                Input::Stereo(distance) => universe.set_stereo(distance),
+                Input::DepthOrder(order) => universe.depth_order = order,
+                Input::Layout(ref layout) => universe.layout = layout.clone(),
+                Input::Ticks(count, delta) => (0..count).for_each(|_| universe.tick(delta)),
+                Input::Roll(roll) => universe.set_camera_roll_vel(roll),
+                Input::Pitch(pitch) => universe.set_camera_pitch_vel(pitch),
+                Input::Yaw(yaw) => universe.set_camera_yaw_vel(yaw),
+                Input::Thrust(speed) => universe.set_thrust(speed),
+                Input::Strafe(speed) => universe.set_strafe(speed),
+                Input::Lift(speed) => universe.set_lift(speed),
+                Input::Resize(width, height) => universe.set_size(width, height),
+                Input::Flight(model) => universe.set_flight_model(&model)?,
+                Input::Position(pos) => universe.set_position(pos.x, pos.y, pos.z),
+                Input::Velocity(vel) => universe.set_velocity(vel.x, vel.y, vel.z),
+                Input::Euler(rpy) => universe.set_orientation_euler(rpy.x, rpy.y, rpy.z),
+                Input::LookAt(point, up) => {
+                    universe.look_at(point.x, point.y, point.z, up.x, up.y, up.z);
+                }
+            }
+        }
+        Ok(universe)
+    }
+
+    fn session(&self) -> Session {
+        Session {
+            width: self.width,
+            height: self.height,
+            time: self.time,
+            origin: self.chunk_store.origin(),
+            camera: self.camera.clone(),
+            indexed: self.chunk_store.index().is_some(),
+            arrays: self.chunk_store.arrays().is_some(),
+            overlay: self.chunk_store.overlay().to_bytes(),
+            path: self.path.to_text()
+        }
+    }
+
+    fn restore(&mut self, session: Session) -> Result<(), String> {
+        let overlay = Overlay::from_bytes(&session.overlay)?;
+        let path = CameraPath::from_text(&session.path)?;
+
+        let mut chunk_store = ChunkStore::start(&session.camera, session.origin, overlay);
+        chunk_store.set_indexed(session.indexed);
+        chunk_store.set_arrays(session.arrays);
+        chunk_store.set_all_round(self.projection != ProjectionMode::Rectilinear);
+        *self = Universe {
+            previous: session.camera.clone(),
+            previous_origin: session.origin,
+            camera: session.camera,
+            chunk_store,
+            autopilot: None,
+            orbit: None,
+            path,
+            path_time: None,
+            jump: None,
+            recording: None,
+            contacts: Contacts::default(),
+            projection: self.projection, //How it's drawn is up to the viewer, not the session.
+            stereo: self.stereo,
+            layout: self.layout.clone(),
+            depth_order: self.depth_order,
+            projected: RefCell::default(),
+            time: session.time,
+            width: session.width,
+            height: session.height
+        };
+        Ok(())
+    }
+
+    //Writes the layout's fields for each star. In stereo the left eye's stars come first, then the right's.
+    fn project_into(&self, arr: &mut [f32], layout: &Layout) -> u32 {
+        let mut previous = self.previous.clone(); //Moved to the current floating origin.
+        previous.pos += (self.previous_origin - self.chunk_store.origin()).corner();
+        if self.stereo <= 0.0 {
+            return self.project_eye(arr, layout, (&self.camera, &previous), 0, self.width);
+        }
+        let half = self.width/2;
+        let eye = |camera: &Camera, side: f32, width: u32| {
+            let mut eye = camera.clone();
+            eye.pos += camera.ori.get_mat().to_vectors_vert()[0]*(side*self.stereo/2.0);
+            eye.rvp = Viewport::fov_maxbound(eye.rvp.get_fov(), max(width, self.height) as f32);
+            eye
+        };
+        let (left_eye, left_previous) = (eye(&self.camera, -1.0, half), eye(&previous, -1.0, half));
+        let left = self.project_eye(arr, layout, (&left_eye, &left_previous), 0, half);
+        let used = left as usize*layout.stride();
+        let right = self.width - half;
+        let (right_eye, right_previous) = (eye(&self.camera, 1.0, right), eye(&previous, 1.0, right));
+        left + self.project_eye(&mut arr[used..], layout, (&right_eye, &right_previous), half, right)
+    }
+
+    //Draws what one eye sees into the part of the screen from left to left + width. eyes are where it is now and where
+    //it was the tick before.
+    fn project_eye(&self, arr: &mut [f32], layout: &Layout, eyes: (&Camera, &Camera), left: u32, width: u32) -> u32 {
+        let (vp, was) = (&eyes.0.rvp, &eyes.1.rvp); //The fov can change between ticks.
+        match self.projection {
+            ProjectionMode::Rectilinear => self.project_with(arr, layout, eyes, (left, width), (Rectilinear::new(vp), Rectilinear::new(was))),
+            ProjectionMode::Fisheye(fov) => self.project_with(arr, layout, eyes, (left, width), (Fisheye::new(vp, fov), Fisheye::new(was, fov))),
+            ProjectionMode::Equirectangular => self.project_with(arr, layout, eyes, (left, width), (Equirectangular::new(vp), Equirectangular::new(was))),
+            ProjectionMode::Stereographic(fov) => self.project_with(arr, layout, eyes, (left, width), (Stereographic::new(vp, fov), Stereographic::new(was, fov)))
+        }
+    }
+
+    fn project_with<P: Projection + Copy>(&self, arr: &mut [f32], layout: &Layout, (eye, previous): (&Camera, &Camera), (left, width): (u32, u32), (projection, was): (P, P)) -> u32 {
+        let mut index = 0usize;
+        let projector = Projector::new(eye, projection);
+        let previous = Projector::new(previous, was);
+
+        let mut render_star_count = 0;
+        let max_dist = eye.cvp.get_alpha() / eye.rvp.get_alpha();
+        let alpha = eye.rvp.get_alpha();
+        let sorting = self.depth_order != DepthOrder::None;
+        let mut scales = vec!(); //For sorting.
+        //star is left out by the fast path, which only draws stars as they are now.
+        let mut emit = |p: PointVector, star: Option<&PointVector>, id: Option<StarId>| { //crash here caused by out of bounds
+            let ax = p.y + (width/2) as f32;
+            let ay = p.z + (self.height/2) as f32;
+            if
+                p.x > 0.0 &&
+                (1.0/p.x) < max_dist &&
+                ax >= 0.0 && ax < width as f32 &&
+                ay >= 0.0 && ay < self.height as f32
+            {
+                let scale = p.x/50.0; //Moved scale here to prevent a /0 crash.
+                let offset = scale/2.0;
+                let (x, y) = (ax-offset + left as f32, ay-offset);
+                let (shift, beaming) = star.map_or((1.0, 1.0), |star| projector.doppler(star));
+                let (prev_x, prev_y) = match star.filter(|_| layout.needs_previous()).and_then(|star| previous.project_point(star)) {
+                    Some(q) if q.x > 0.0 => (q.y + (width/2) as f32 - offset + left as f32, q.z + (self.height/2) as f32 - offset),
+                    _ => (x, y) //No streak for stars that were behind the camera.
+                };
+                let star = Projected { id, scale: p.x, alpha, x, y, prev_x, prev_y, size: scale, shift, beaming };
+                layout.write(&mut arr[index..index + layout.stride()], &star);
+                if sorting {
+                    scales.push(p.x);
+                }
+                render_star_count += 1;
+                index += layout.stride();
+            }
+        };
+        let mut project = |star: &PointVector, id: Option<StarId>| {
+            if let Some(p) = projector.project_point(star) {
+                emit(p, Some(star), id);
+            }
+        };
+
+        //The fast paths don't know about aberration or anything but a perspective view.
+        let simple = !projector.is_relativistic() && self.projection == ProjectionMode::Rectilinear;
+        if layout.needs_ids() { //Neither fast path knows which star is which.
+            self.chunk_store.for_each_star(|id, star| project(star, Some(id)));
+        } else if let Some(arrays) = self.chunk_store.arrays().filter(|_| simple && !layout.needs_previous()) {
+            let mut projected = self.projected.borrow_mut();
+            let [scales, xs, ys] = &mut *projected;
+            let dirs = eye.ori.get_mat().to_vectors_vert();
+            arrays.project(eye.pos, &dirs, eye.rvp.get_alpha(), scales, xs, ys);
+            for ((scale, x), y) in scales.iter().zip(xs.iter()).zip(ys.iter()) {
+                emit(PointVector::new(*scale, *x, *y), None, None);
+            }
+        } else if let Some(octree) = self.chunk_store.index() {
+            let frustum = Frustum::new(eye, width, self.height);
+            octree.visit(|lo, hi| !simple || frustum.intersects_box(lo, hi), |star| project(star, None));
+        } else {
+            self.chunk_store.iter().for_each(|star| project(star, None));
+        }
+
+        sort_by_depth(&mut arr[..index], layout.stride(), &scales, self.depth_order);
+        render_star_count
+    }
+
+    //For benches/projection.rs. Projects every loaded star through the rectilinear Projector, or with inverting, by
+    //building and inverting a matrix for each star as Projector::project_point used to. Returns how many land on screen.
+    pub fn count_on_screen(&self, inverting: bool) -> u32 {
+        let camera = &self.camera;
+        let dirs = camera.ori.get_mat().to_vectors_vert();
+        let (xcol, ycol, total) = (dirs[0]*-1.0, dirs[1], dirs[2]*camera.rvp.get_alpha());
+        let projector = Projector::new(camera, Rectilinear::new(&camera.rvp));
+        let mut count = 0;
+        self.chunk_store.iter().for_each(|star| {
+            let p = if inverting {
+                Matrix3::from_vectors_vert([*star - camera.pos, xcol, ycol]).invert().map(|m| m.solve_vec(total))
+            } else {
+                projector.project_point(star)
+            };
+            count += p.is_some_and(|p| p.x > 0.0 && p.y.abs() < (self.width/2) as f32 && p.z.abs() < (self.height/2) as f32) as u32;
+        });
+        count
+    }
+
+    fn thrust_scale(&self) -> f32 { //Cruise flight speeds up away from stars and slows down near them.
+        let cruise = self.camera.flight.cruise_distance;
+        if cruise <= 0.0 {
+            return 1.0;
+        }
+        let range = cruise*CRUISE_RANGE;
+        let dist = self.chunk_store.masses_near(self.camera.pos, range).iter()
+            .map(|(star, _)| (*star - self.camera.pos).magnitude())
+            .fold(range, f32::min);
+        (dist/cruise).max(MIN_THRUST_SCALE)
+    }
+
+    fn pulling_stars(&self, delta: f32) -> Vec<(PointVector, f32)> { //The stars free flight needs to know about this tick.
+        let flight = &self.camera.flight;
+        let mut range = 0.0f32;
+        if flight.gravity != 0.0 {
+            range = GRAVITY_RANGE;
+        }
+        if flight.avoidance > 0.0 {
+            range = range.max(self.camera.vel.magnitude()*delta + MAX_STAR_RADIUS*flight.avoidance.max(1.0));
+        }
+        if range > 0.0 {
+            self.chunk_store.masses_near(self.camera.pos, range)
+        } else {
+            vec!()
+        }
+    }
+
+    fn check_contacts(&mut self, start: PointVector) { //Works out which stars the camera moved into or through.
+        let end = self.camera.pos;
+        let half = (end - start)/2.0;
+        let touching = self.chunk_store.stars_near(start + half, half.magnitude() + MAX_STAR_RADIUS).into_iter()
+            .filter(|(id, star)| entry(start, end, *star, star_radius(Chunk::star_mass(id.chunk(), id.index))).is_some())
+            .map(|(id, _)| id)
+            .collect();
+        self.contacts.update(touching);
+    }
+
+    fn record(&mut self, input: Input) {
+        if let Some(log) = &mut self.recording {
+            log.record(input);
+        }
+    }
+
+    fn play_path(&mut self, delta: f32) { //Puts the camera where the path says instead of running the flight model.
+        let Some(time) = self.path_time.map(|t| t + delta) else {
+            return;
+        };
+        if let Some(key) = self.path.sample(time) {
+            let pos = self.chunk_store.to_relative(key.pos);
+            if delta > 0.0 {
+                self.camera.vel = (pos - self.camera.pos)/delta; //Carried on when the path ends.
+            }
+            self.camera.pos = pos;
+            self.camera.ori = Orientation::from_quaternion(key.rot);
+            self.camera.rpy_vel = PointVector::zeros();
+            self.camera.target_rpy_vel = PointVector::zeros();
+            self.camera.cut_thrust();
+            self.camera.set_fov(key.fov);
+        }
+        self.path_time = if time < self.path.end_time() {Some(time)} else {None};
+    }
+
+    fn play_jump(&mut self, delta: f32) { //Holds the camera still while the target is generated, then moves it there.
+        let Some(jump) = &mut self.jump else {
+            return;
+        };
+        let ready = jump.arrived() || {
+            let pos = (jump.target - jump.origin.world_corner()).to_point();
+            let cvp = Viewport::fov_alpha(WARP_FOV, self.camera.cvp.get_alpha()); //The widest the view gets at the target.
+            self.chunk_store.prefetch(pos, &self.camera.ori, &cvp, jump.origin, PREFETCH_CHUNKS)
+        };
+        match jump.tick(delta, ready) {
+            JumpStep::Warping => {},
+            JumpStep::Arrive => {
+                self.chunk_store.rebase(jump.origin);
+                self.camera.pos = self.chunk_store.to_relative(jump.target);
+                self.camera.gravity = PointVector::zeros();
+                (self.previous, self.previous_origin) = (self.camera.clone(), jump.origin); //As keep_previous does.
+            },
+            JumpStep::Done => {
+                self.camera.set_fov(self.camera.flight.fov(0.0));
+                self.jump = None;
+                self.chunk_store.clear_prefetch();
+                return;
+            }
+        }
+        self.camera.set_fov(jump.fov(self.camera.flight.fov(0.0)));
+    }
+
+    //Remembers where the camera is for drawing streaks. Called before each tick, and after the camera is moved in one go
+    //so that doesn't streak.
+    fn keep_previous(&mut self) {
+        self.previous = self.camera.clone();
+        self.previous_origin = self.chunk_store.origin();
+    }
+
+    fn rebase_origin(&mut self) { //Keeps the camera near the floating origin so f32 positions stay precise.
+        let offset = ChunkVector::from_point(self.camera.pos);
+        if offset.x.abs() > REBASE_CHUNKS || offset.y.abs() > REBASE_CHUNKS || offset.z.abs() > REBASE_CHUNKS {
+            let shift = self.chunk_store.rebase(self.chunk_store.origin() + offset);
+            self.camera.pos = self.camera.pos - shift;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vector3::CHUNK_SIZE;
+
+    use super::*;
+
+    #[test]
+    fn rejects_bad_flight_models() {
+        let mut universe = Universe::new(800, 600, 1536.0);
+        for model in [FlightModel { mass: 0.0, ..FlightModel::arcade() }, FlightModel { rpy_factor: -1.0, ..FlightModel::arcade() }, FlightModel { gravity: f32::NAN, ..FlightModel::arcade() }, FlightModel { base_fov: 3.5, max_fov: 3.5, ..FlightModel::arcade() }] {
+            assert!(universe.set_flight_model(&model).is_err());
+        }
+        assert_eq!(universe.get_flight_model(), FlightModel::arcade());
+        for name in ["arcade", "newtonian", "assisted", "cruise"] {
+            universe.set_flight_preset(name).unwrap();
+        }
+        universe.set_thrust(1.0);
+        (0..10).for_each(|_| universe.tick(1.0));
+        assert!(universe.get_position().iter().all(|c| c.is_finite()));
+    }
+
+    #[test]
+    fn rejects_bad_sessions() {
+        let mut universe = Universe::new(800, 600, 1536.0);
+        universe.set_position(10.0, 20.0, 30.0);
+        let saved = universe.save_session_json();
+        assert!(universe.load_session_json(&"[".repeat(200000)).is_err());
+        for (good, bad) in [("\"width\": 800", "\"width\": 0"), ("\"mass\": 100", "\"mass\": 0"), ("\"rpy_factor\": 20", "\"rpy_factor\": 0"), ("\"width\": 800", "\"width\": +800"), ("\"time\": 0", "\"time\": 1e999"), ("\"origin\": [0, 0, 0]", "\"origin\": [0.5, 0, 0]"), ("\"origin\": [0, 0, 0]", "\"origin\": [0, 3000000000, 0]")] {
+            assert!(saved.contains(good), "{}", good);
+            assert!(universe.load_session_json(&saved.replace(good, bad)).is_err(), "{}", bad);
+        }
+
+        let mut bytes = universe.save_session();
+        bytes[6..10].copy_from_slice(&0u32.to_le_bytes()); //The width, after the header and generator version.
+        assert!(universe.load_session(&bytes).is_err());
+        assert_eq!(universe.get_position(), vec!(10.0, 20.0, 30.0));
+        universe.load_session_json(&saved).unwrap();
+
+        let key = "key 0 0 0 0 1 0 0 0 90";
+        for bad in ["key 0 nan 0 0 1 0 0 0 90", "key 0 0 0 0 1 0 0 0 inf", "key 0 0 0 0 1 0 0 0 0", "key 0 0 0 0 1 0 0 0 180"] {
+            assert!(universe.load_camera_path(&format!("startest-path 1\n{}\n", bad)).is_err(), "{}", bad);
+        }
+        universe.load_camera_path(&format!("startest-path 1\n{}\n", key)).unwrap();
+        let saved = universe.save_session_json();
+        assert!(universe.load_session_json(&saved.replace(key, "key 0 nan 0 0 1 0 0 0 90")).is_err());
+        universe.load_session_json(&saved).unwrap();
+    }
+
+    #[test]
+    fn flies_far_from_home() {
+        let mut universe = Universe::new(800, 600, 1536.0);
+        let far = 1e6*CHUNK_SIZE as f64;
+        universe.set_position(-far, far, far);
+        universe.set_thrust(1.0);
+        (0..5).for_each(|_| universe.tick(1.0));
+        let mut arr = vec!(0.0; universe.count_stars()*3);
+        assert!(universe.project_stars(&mut arr) > 0);
+        assert!(universe.get_position()[0] < -far + 1000.0);
+    }
+
+    #[test]
+    fn replays_exactly() {
+        let mut universe = Universe::new(800, 600, 1536.0);
+        universe.set_projection("fisheye", 4.0).unwrap();
+        universe.set_stereo(2.0);
+        universe.set_depth_order("back_to_front").unwrap();
+        universe.set_output_layout("id, x, y, prev_x, prev_y, brightness").unwrap();
+        universe.set_flight_preset("newtonian").unwrap();
+        universe.start_recording();
+        universe.set_thrust(5.0);
+        universe.set_camera_yaw_vel(0.02);
+        (0..30).for_each(|_| universe.tick(1.0));
+        universe.set_projection("stereographic", 3.0).unwrap();
+        universe.set_output_layout("depth, x, y, size, shift").unwrap();
+        universe.set_camera_pitch_vel(-0.01);
+        universe.set_size(640, 480);
+        (0..30).for_each(|_| universe.tick(0.5));
+        let replayed = Universe::replay(&universe.stop_recording()).unwrap();
+
+        assert_eq!(universe.get_position(), replayed.get_position());
+        assert_eq!(universe.get_orientation_euler(), replayed.get_orientation_euler());
+        let project = |u: &Universe, layout: bool| {
+            let stride = if layout {u.layout.stride()} else {3};
+            let mut arr = vec!(0.0; u.count_stars()*stride*2);
+            let count = if layout {u.project_stars_layout(&mut arr)} else {u.project_stars(&mut arr)};
+            arr.truncate(count as usize*stride);
+            arr
+        };
+        for layout in [false, true] {
+            let stars = project(&universe, layout);
+            assert!(!stars.is_empty());
+            assert_eq!(stars, project(&replayed, layout));
+        }
+    }
+
+    #[test]
+    fn jumps_arrive_on_prefetched_chunks() {
+        let mut universe = Universe::new(800, 600, 1536.0);
+        let target = [5e7, -3e6, 2e7];
+        universe.jump_to(target[0], target[1], target[2]);
+        let mut arrived = false;
+        for _ in 0..1000 {
+            universe.tick(1.0);
+            if !arrived && universe.get_position() == target {
+                arrived = true;
+                let stats = universe.stats();
+                assert_eq!(stats.chunks_generated, 0);
+                assert_eq!(stats.chunks_reused, stats.loaded_chunks);
+            }
+            if !universe.is_jumping() {
+                break;
+            }
+        }
+        assert!(arrived && !universe.is_jumping());
+        assert!(universe.count_stars() > 0);
+    }
+
+    #[test]
+    fn autopilot_stops_at_the_standoff() {
+        let mut universe = Universe::new(800, 600, 256.0); //Only the flying matters, so keep the chunks few.
+        for (target, standoff, preset) in [([300.0, -200.0, 900.0], 50.0, "arcade"), ([-400.0, 100.0, -300.0], 20.0, "newtonian"), ([0.0, 0.0, 300.0], -50.0, "arcade")] {
+            universe.set_flight_preset(preset).unwrap();
+            universe.autopilot_to(target[0], target[1], target[2], standoff);
+            let mut ticks = 0;
+            while universe.is_autopilot_active() && ticks < 5000 {
+                universe.tick(1.0);
+                ticks += 1;
+            }
+            assert!(!universe.is_autopilot_active(), "{} still flying", preset);
+            let pos = universe.get_position();
+            let dist = (0..3).map(|i| (pos[i] - target[i]).powi(2)).sum::<f64>().sqrt();
+            assert!((dist - standoff.max(0.0) as f64).abs() < 1.0, "{} stopped {} away", preset, dist);
+            assert!(universe.get_velocity().iter().map(|v| v*v).sum::<f32>().sqrt() < 0.05);
+        }
+    }
+
+    #[test]
+    fn reports_contacts_and_avoids_stars() {
+        let mut universe = Universe::new(800, 600, 256.0);
+        let star = universe.add_star(10.0, 20.0, 500.0, String::new());
+        let radius = star_radius(Chunk::star_mass(star.chunk(), star.index)) as f64;
+        let contacts = |u: &mut Universe| std::iter::from_fn(|| u.next_star_contact()).filter(|c| c.star == star).map(|c| c.entered).collect::<Vec<_>>();
+
+        universe.set_position(10.0, 20.0, 480.0);
+        universe.set_velocity(0.0, 0.0, 40.0); //Through the star in one tick.
+        universe.tick(1.0);
+        assert_eq!(contacts(&mut universe), vec!(true));
+        universe.tick(1.0);
+        assert_eq!(contacts(&mut universe), vec!(false));
+
+        universe.set_flight_model(&FlightModel { avoidance: 4.0, ..FlightModel::arcade() }).unwrap();
+        universe.set_position(10.0, 20.0, 400.0);
+        universe.set_velocity(0.0, 0.0, 30.0);
+        let dist = |u: &Universe| {
+            let pos = u.get_position();
+            (pos[0] - 10.0).hypot(pos[1] - 20.0).hypot(pos[2] - 500.0)
+        };
+        universe.set_thrust(10.0);
+        for _ in 0..50 {
+            universe.tick(1.0);
+            assert!(dist(&universe) > radius);
+        }
+        assert!(dist(&universe) < radius*4.0); //Pressed up against it.
+        assert_eq!(contacts(&mut universe), vec!());
+    }
+
+    #[test]
+    fn aberrates_and_shifts_at_speed() {
+        let mut universe = Universe::new(800, 600, 512.0);
+        universe.set_flight_model(&FlightModel { light_speed: 10.0, ..FlightModel::arcade() }).unwrap();
+        universe.set_output_layout("id, x, y, size, shift, beaming").unwrap();
+        let project = |u: &Universe| {
+            let mut arr = vec!(0.0; u.count_stars()*10);
+            let count = u.project_stars_layout(&mut arr) as usize;
+            arr[..count*10].chunks(10).map(|s| {
+                let id = StarId { cx: s[0] as i32, cy: s[1] as i32, cz: s[2] as i32, index: s[4] as u32 };
+                let from_middle = (s[5] + s[7]/2.0 - 400.0).hypot(s[6] + s[7]/2.0 - 300.0);
+                (id, from_middle, s[8], s[9])
+            }).collect::<Vec<_>>()
+        };
+        let at_rest = project(&universe);
+        assert!(at_rest.iter().all(|(_, _, shift, beaming)| *shift == 1.0 && *beaming == 1.0));
+
+        universe.set_velocity(0.0, 0.0, 8.0);
+        let moving = project(&universe);
+        let (beta, gamma) = (0.8f32, 1.0/(1.0f32 - 0.64).sqrt());
+        let mut matched = 0;
+        for (id, from_middle, shift, beaming) in &moving {
+            let star = universe.chunk_store.star_position(id).unwrap();
+            let expected = gamma*(1.0 + beta*star.z/star.magnitude());
+            assert!((shift - expected).abs() < 1e-3 && (beaming - expected.powi(4)).abs() < 1e-2*expected.powi(4));
+            if let Some((_, rest, _, _)) = at_rest.iter().find(|(i, ..)| i == id) {
+                assert!(from_middle < rest || *rest < 1.0); //Bunched up towards where the camera is heading.
+                matched += 1;
+            }
+        }
+        assert!(matched > 0 && moving.len() > at_rest.len());
+    }
+
+    #[test]
+    fn flight_assist_cancels_drift() {
+        let fly = |preset: &str| {
+            let mut universe = Universe::new(800, 600, 256.0);
+            universe.set_flight_preset(preset).unwrap();
+            universe.set_velocity(3.0, -2.0, 1.0);
+            universe.set_thrust(5.0); //Forward, which assist leaves alone.
+            (0..100).for_each(|_| universe.tick(1.0));
+            universe.get_velocity()
+        };
+        let (newtonian, assisted) = (fly("newtonian"), fly("assisted"));
+        assert_eq!(newtonian[..2], [3.0, -2.0]);
+        assert!(assisted[0].abs() < 0.01 && assisted[1].abs() < 0.01);
+        assert!((assisted[2] - newtonian[2]).abs() < 1e-3 && assisted[2] > 5.0);
+    }
+
+    #[test]
+    fn stereo_draws_each_eye_on_its_half() {
+        let mut universe = Universe::new(800, 600, 512.0);
+        universe.set_output_layout("id, x, y, size").unwrap();
+        universe.set_stereo(20.0);
+        let mut arr = vec!(0.0; universe.count_stars()*8*2);
+        let count = universe.project_stars_layout(&mut arr) as usize;
+        let stars: Vec<_> = arr[..count*8].chunks(8).map(|s| (s[..5].to_vec(), s[5] + s[7]/2.0, s[6] + s[7]/2.0)).collect();
+
+        let left = stars.iter().take_while(|(_, x, _)| *x < 400.0).count();
+        assert!(left > 0 && left < count);
+        assert!(stars[left..].iter().all(|(_, x, _)| (400.0..800.0).contains(x))); //Left eye first, then the right.
+        let mut matched = 0;
+        for (id, x, y) in &stars[..left] {
+            if let Some((_, right_x, right_y)) = stars[left..].iter().find(|(i, ..)| i == id) {
+                assert!(x - (right_x - 400.0) > 0.0 && (y - right_y).abs() < 1e-3); //The left eye sees things further right.
+                matched += 1;
+            }
+        }
+        assert!(matched > left/2);
+    }
+
+    #[test]
+    fn streaks_start_where_stars_were() {
+        let mut universe = Universe::new(800, 600, 512.0);
+        universe.set_output_layout("id, x, y, prev_x, prev_y").unwrap();
+        let project = |u: &Universe| {
+            let mut arr = vec!(0.0; u.count_stars()*9);
+            let count = u.project_stars_layout(&mut arr) as usize;
+            arr[..count*9].chunks(9).map(|s| (s[..5].to_vec(), [s[5], s[6]], [s[7], s[8]])).collect::<Vec<_>>()
+        };
+        universe.tick(1.0);
+        universe.tick(1.0); //The first tick sets the fov from the flight model.
+        assert!(project(&universe).iter().all(|(_, now, before)| now == before));
+
+        universe.set_flight_preset("newtonian").unwrap();
+        universe.set_velocity(0.0, 0.0, 300.0); //Fast enough to move the floating origin.
+        let origin = universe.chunk_store.origin();
+        let mut streaked = 0;
+        for _ in 0..6 {
+            let last = project(&universe);
+            universe.tick(1.0);
+            for (id, now, before) in project(&universe) {
+                if let Some((_, was, _)) = last.iter().find(|(i, ..)| *i == id) {
+                    assert!((before[0] - was[0]).abs() < 0.05 && (before[1] - was[1]).abs() < 0.05, "{:?} != {:?}", before, was);
+                    streaked += (now != before) as usize;
+                }
+            }
+        }
+        assert!(streaked > 0 && universe.chunk_store.origin() != origin);
+
+        universe.set_position(0.0, 0.0, 0.0);
+        assert!(project(&universe).iter().all(|(_, now, before)| now == before));
+    }
+}
@@ -1,11 +1,13 @@
-use std::{ops::{Add, Mul, Sub, Div, AddAssign, Neg}};
+use std::{ops::{Add, Mul, Sub, Div, AddAssign, Neg}, hash::{Hash, Hasher}};
 
 pub type PointVector = Vector3<f32>;
 pub type ChunkVector = Vector3<i32>;
+pub type WorldVector = Vector3<f64>; //Absolute position, only used at the edges. Everything else is relative to the floating origin.
 
 
 pub const CHUNK_SIZE: u32 = 128;
 const CHUNK_F32: f32 = CHUNK_SIZE as f32;
+const CHUNK_F64: f64 = CHUNK_SIZE as f64;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Vector3<T> {
@@ -135,6 +137,27 @@ impl PointVector {
     pub fn zeros() -> PointVector {
         PointVector::new(0.0, 0.0, 0.0)
     }
+
+    pub fn magnitude(&self) -> f32 {
+        (*self * *self).sqrt()
+    }
+
+    pub fn normalised(&self) -> PointVector { //Returns zero for a zero vector.
+        let length = self.magnitude();
+        if length == 0.0 {
+            *self
+        } else {
+            *self / length
+        }
+    }
+
+    pub fn cross(&self, rhs: PointVector) -> PointVector {
+        PointVector::new(
+            self.y*rhs.z - self.z*rhs.y,
+            self.z*rhs.x - self.x*rhs.z,
+            self.x*rhs.y - self.y*rhs.x
+        )
+    }
 }
 
 
@@ -146,6 +169,32 @@ impl ChunkVector {
             z: (v.z/CHUNK_F32).floor() as i32
         }
     }
+
+    pub fn from_world(v: WorldVector) -> ChunkVector{
+        ChunkVector {
+            x: (v.x/CHUNK_F64).floor() as i32,
+            y: (v.y/CHUNK_F64).floor() as i32,
+            z: (v.z/CHUNK_F64).floor() as i32
+        }
+    }
+
+    pub fn corner(&self) -> PointVector { //Only precise for small vectors, so use it on differences between chunks.
+        PointVector::new(self.x as f32, self.y as f32, self.z as f32) * CHUNK_F32
+    }
+
+    pub fn world_corner(&self) -> WorldVector {
+        WorldVector::new(self.x as f64, self.y as f64, self.z as f64) * CHUNK_F64
+    }
+}
+
+impl WorldVector {
+    pub fn from_point(v: PointVector) -> WorldVector {
+        WorldVector::new(v.x as f64, v.y as f64, v.z as f64)
+    }
+
+    pub fn to_point(self) -> PointVector {
+        PointVector::new(self.x as f32, self.y as f32, self.z as f32)
+    }
 }
 
 impl PartialEq for ChunkVector {
@@ -153,3 +202,11 @@ impl PartialEq for ChunkVector {
         self.x == other.x && self.y == other.y && self.z == other.z
     }
 }
+
+impl Eq for ChunkVector {}
+
+impl Hash for ChunkVector {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.to_array().hash(state);
+    }
+}
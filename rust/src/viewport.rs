@@ -1,3 +1,4 @@
+#[derive(Clone)]
 pub struct Viewport {
     fov: f32,
     alpha: f32,
@@ -41,4 +42,8 @@ impl Viewport {//Triginomitory and stuff.
         self.alpha
     }
 
+    pub fn get_fov(&self) -> f32 {
+        self.fov
+    }
+
 }
\ No newline at end of file